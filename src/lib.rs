@@ -15,33 +15,58 @@
 // You should have received a copy of the GNU Lesser General Public License
 // along with udev-rs; If not, see <http://www.gnu.org/licenses/>.
 
-#![feature(unsafe_destructor, if_let, globs)]
+#![feature(if_let, globs, struct_variant)]
 
 extern crate alloc;
 extern crate libc;
 
-pub use udev::udev::Udev;
+pub use udev::udev::{Udev, UdevFactory};
+pub use udev::error::Error;
+pub use udev::builder::UdevBuilder;
 
 mod udev;
 
+#[cfg(feature = "libudev")]
 pub mod hwdb {
     pub use udev::hwdb::{
         Hwdb,
         Query,
 
         HwdbIterator,
+        HwdbBytesIterator,
     };
 }
 pub mod device {
+    pub use udev::snapshot::DeviceSnapshot;
+    pub use udev::devnum::{Devnum, Type};
     pub use udev::device::{
         Device,
-        Devnum,
-        Type,
+        Info,
 
         TagIterator,
+        TagBytesIterator,
         AttributeIterator,
+        AttributeBytesIterator,
         DevlinkIterator,
         PropertyIterator,
+        PropertyBytesIterator,
+    };
+}
+pub mod attribute {
+    pub use udev::attribute::{
+        FromAttribute,
+        Hex,
+        ByteSize,
+        Selection,
+    };
+}
+pub mod db {
+    pub use udev::db::Record;
+}
+pub mod filter {
+    pub use udev::filter::{
+        Filter,
+        Matchable,
     };
 }
 pub mod enumerator {
@@ -53,27 +78,58 @@ pub mod enumerator {
     };
 }
 pub mod monitor {
+    pub use udev::event::{Event, Action};
     pub use udev::monitor::{
         Monitor,
-        Event,
-        Action,
+        Mode,
 
         MonitorIterator,
+        DrainIterator,
     };
 }
+#[cfg(feature = "sysfs")]
+pub mod netlink {
+    pub use udev::netlink::{
+        NetlinkMonitor,
+        MonitorSender,
+        Group,
+        Message,
+        Header,
+
+        NetlinkIterator,
+    };
+}
+pub mod queue {
+    pub use udev::queue::Queue;
+}
+pub mod trigger {
+    pub use udev::trigger::Trigger;
+}
+pub mod watcher {
+    pub use udev::watcher::{
+        DeviceWatcher,
+        Source,
+        Delta,
+    };
+    pub use udev::watcher::UdevSource;
+}
 
 #[cfg(test)]
 mod test {
-    use Udev;
+    use {Udev, UdevFactory};
 
     #[test]
     fn test_ttys() {
         let udev = Udev::new();
         let mut vec = Vec::with_capacity(64);
         let mut found_tty = false;
-        for dev in udev.enumerator().match_subsystem("tty").scan_devices().iter() {
+        let enumerator = udev.enumerator()
+            .and_then(|e| e.match_subsystem("tty"))
+            .and_then(|e| e.scan_devices())
+            .unwrap();
+        for dev in enumerator.iter() {
             assert!(dev.subsystem().unwrap() == "tty");
-            if dev.sysname().starts_with("tty") {
+            if dev.sysname().map_or(false, |name| name.starts_with("tty")) {
                 match dev.sysnum() {
                     Some(num) => vec.push(num),
                     None => {
@@ -87,4 +143,36 @@ mod test {
         vec.sort();
         assert!(vec.into_iter().zip(range(0u64, 64u64)).all(|(i, j)| i == j));
     }
+
+    #[test]
+    fn test_snapshot_across_threads() {
+        let factory = UdevFactory::new();
+        let (tx, rx) = channel();
+
+        let thread_factory = factory.clone();
+        spawn(proc() {
+            let udev = thread_factory.create().unwrap();
+            let enumerator = udev.enumerator()
+                .and_then(|e| e.match_subsystem("tty"))
+                .and_then(|e| e.scan_devices())
+                .unwrap();
+            for dev in enumerator.iter() {
+                tx.send(dev.snapshot());
+            }
+        });
+
+        let udev = factory.create().unwrap();
+        let mut count = 0u;
+        for snapshot in rx.iter() {
+            assert!(snapshot.subsystem() == Some("tty"));
+            let dev = snapshot.device(&udev).unwrap();
+            assert!(dev.syspath() == *snapshot.syspath());
+            let properties: Vec<(Vec<u8>, Option<Vec<u8>>)> = dev.iter_properties_bytes().map(|(key, value)| {
+                (key.to_vec(), value.map(|value| value.to_vec()))
+            }).collect();
+            assert_eq!(snapshot.properties(), properties.as_slice());
+            count += 1;
+        }
+        assert!(count > 0);
+    }
 }
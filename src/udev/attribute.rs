@@ -0,0 +1,172 @@
+// This file is part of udev-rs.
+// 
+// Copyright 2014 Steven Allen <steven@stebalien.com>
+// 
+// udev-rs is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation; either version 2.1 of the License, or
+// (at your option) any later version.
+// 
+// udev-rs is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+// 
+// You should have received a copy of the GNU Lesser General Public License
+// along with udev-rs; If not, see <http://www.gnu.org/licenses/>.
+
+use std::u64;
+
+use udev::util;
+
+/// A type that can be parsed from a sysfs attribute.
+///
+/// See `Device::attribute_as`.
+pub trait FromAttribute {
+    /// Parse an attribute value.
+    ///
+    /// Leading and trailing whitespace (including the trailing newline most attributes have) is
+    /// stripped before this is called.
+    fn from_attribute(value: &str) -> Option<Self>;
+}
+
+/// A hexadecimal integer attribute.
+///
+/// E.g. `idVendor`, `idProduct`
+#[deriving(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Show)]
+pub struct Hex(pub u64);
+
+/// A size in bytes.
+///
+/// Accepts plain integers and integers with a binary (1024-based) unit suffix: `K`, `M`, `G`, `T`
+/// optionally followed by `B` or `iB` (e.g. `4096`, `64K`, `2MiB`).
+#[deriving(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Show)]
+pub struct ByteSize(pub u64);
+
+/// A list of choices with one (optionally) selected.
+///
+/// E.g. `[none] mq-deadline kyber` (`queue/scheduler`)
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub struct Selection {
+    /// All available choices.
+    pub choices: Vec<String>,
+    /// The index of the selected choice (if any).
+    pub selected: Option<uint>,
+}
+
+impl Selection {
+    /// Get the selected choice.
+    pub fn selected<'s>(&'s self) -> Option<&'s str> {
+        self.selected.map(|i| self.choices[i].as_slice())
+    }
+}
+
+macro_rules! from_attribute_int {
+    ($($t:ty),*) => ($(
+        impl FromAttribute for $t {
+            fn from_attribute(value: &str) -> Option<$t> {
+                from_str(value)
+            }
+        }
+    )*)
+}
+
+from_attribute_int!(u8, u16, u32, u64, uint, i8, i16, i32, i64, int);
+
+impl FromAttribute for bool {
+    fn from_attribute(value: &str) -> Option<bool> {
+        match value {
+            "1" | "Y" | "y" => Some(true),
+            "0" | "N" | "n" => Some(false),
+            _               => None
+        }
+    }
+}
+
+impl FromAttribute for String {
+    fn from_attribute(value: &str) -> Option<String> {
+        Some(value.to_string())
+    }
+}
+
+impl FromAttribute for Hex {
+    fn from_attribute(value: &str) -> Option<Hex> {
+        util::parse_hex(value).map(Hex)
+    }
+}
+
+impl FromAttribute for ByteSize {
+    fn from_attribute(value: &str) -> Option<ByteSize> {
+        let split = value.find(|c: char| c < '0' || c > '9').unwrap_or(value.len());
+        let (number, unit) = (value.slice_to(split), value.slice_from(split).trim());
+        let shift = match unit {
+            ""                      => 0u,
+            "K" | "KB" | "KiB"      => 10,
+            "M" | "MB" | "MiB"      => 20,
+            "G" | "GB" | "GiB"      => 30,
+            "T" | "TB" | "TiB"      => 40,
+            _                       => return None
+        };
+        match from_str::<u64>(number) {
+            Some(n) if n <= u64::MAX >> shift   => Some(ByteSize(n << shift)),
+            _                                   => None
+        }
+    }
+}
+
+impl FromAttribute for Selection {
+    fn from_attribute(value: &str) -> Option<Selection> {
+        let mut selection = Selection { choices: Vec::new(), selected: None };
+        for word in value.words() {
+            if word.starts_with("[") && word.ends_with("]") && word.len() > 1 {
+                if selection.selected.is_some() {
+                    return None;
+                }
+                selection.selected = Some(selection.choices.len());
+                selection.choices.push(word.slice(1, word.len() - 1).to_string());
+            } else {
+                selection.choices.push(word.to_string());
+            }
+        }
+        Some(selection)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FromAttribute, Hex, ByteSize, Selection};
+
+    #[test]
+    fn test_parse_scalars() {
+        assert_eq!(FromAttribute::from_attribute("42"), Some(42u64));
+        assert_eq!(FromAttribute::from_attribute("-1"), Some(-1i32));
+        assert_eq!(FromAttribute::from_attribute("x"), None::<u32>);
+        assert_eq!(FromAttribute::from_attribute("1"), Some(true));
+        assert_eq!(FromAttribute::from_attribute("N"), Some(false));
+        assert_eq!(FromAttribute::from_attribute("046d"), Some(Hex(0x046d)));
+        assert_eq!(FromAttribute::from_attribute("0x1F"), Some(Hex(0x1f)));
+    }
+
+    #[test]
+    fn test_parse_byte_size() {
+        assert_eq!(FromAttribute::from_attribute("4096"), Some(ByteSize(4096)));
+        assert_eq!(FromAttribute::from_attribute("64K"), Some(ByteSize(64 * 1024)));
+        assert_eq!(FromAttribute::from_attribute("2MiB"), Some(ByteSize(2 * 1024 * 1024)));
+        assert_eq!(FromAttribute::from_attribute("2Q"), None::<ByteSize>);
+    }
+
+    #[test]
+    fn test_parse_selection() {
+        let s: Selection = FromAttribute::from_attribute("[none] mq-deadline kyber").unwrap();
+        assert_eq!(s.choices, vec!["none".to_string(), "mq-deadline".to_string(), "kyber".to_string()]);
+        assert_eq!(s.selected(), Some("none"));
+
+        let s: Selection = FromAttribute::from_attribute("always [madvise] never").unwrap();
+        assert_eq!(s.selected, Some(1));
+
+        let s: Selection = FromAttribute::from_attribute("a b").unwrap();
+        assert_eq!(s.selected(), None);
+
+        assert_eq!(FromAttribute::from_attribute("[a] [b]"), None::<Selection>);
+    }
+}
@@ -0,0 +1,184 @@
+// This file is part of udev-rs.
+// 
+// Copyright 2014 Steven Allen <steven@stebalien.com>
+// 
+// udev-rs is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation; either version 2.1 of the License, or
+// (at your option) any later version.
+// 
+// udev-rs is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+// 
+// You should have received a copy of the GNU Lesser General Public License
+// along with udev-rs; If not, see <http://www.gnu.org/licenses/>.
+
+#[cfg(not(feature = "sysfs"))]
+use libc::EOPNOTSUPP;
+
+#[cfg(not(feature = "sysfs"))]
+use udev::error;
+use udev::udev;
+use udev::error::Error;
+use udev::udev::{Udev, UdevFactory, Context};
+#[cfg(feature = "sysfs")]
+use udev::sysfs::Sysfs;
+
+/// Configures where devices are read from.
+///
+/// By default devices are read from `/sys`, device nodes live in `/dev` and udev's runtime data
+/// (its database) is in `/run/udev`. Pointing these at a fixture tree makes it possible to test
+/// device-handling code without real hardware:
+///
+/// ```ignore
+/// let udev = try!(UdevBuilder::new()
+///     .sysfs_root(dir.join("sys"))
+///     .dev_root(dir.join("dev"))
+///     .udev_run_dir(dir.join("run/udev"))
+///     .build());
+/// ```
+///
+/// Only the pure-Rust backend (the `sysfs` feature) can read from other locations; libudev
+/// always reads from the real paths.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub struct UdevBuilder {
+    sysfs_root: Path,
+    dev_root: Path,
+    udev_run_dir: Path,
+}
+
+impl UdevBuilder {
+    /// Create a builder with the default paths.
+    pub fn new() -> UdevBuilder {
+        UdevBuilder {
+            sysfs_root: Path::new("/sys"),
+            dev_root: Path::new("/dev"),
+            udev_run_dir: Path::new("/run/udev"),
+        }
+    }
+
+    /// Set the sysfs mount point (default: `/sys`).
+    ///
+    /// Syspaths (and therefore attribute reads and writes) are under this directory.
+    pub fn sysfs_root(mut self, path: Path) -> UdevBuilder {
+        self.sysfs_root = path;
+        self
+    }
+
+    /// Set the device node directory (default: `/dev`).
+    ///
+    /// Device nodes and devlinks are under this directory.
+    pub fn dev_root(mut self, path: Path) -> UdevBuilder {
+        self.dev_root = path;
+        self
+    }
+
+    /// Set udev's runtime directory (default: `/run/udev`).
+    ///
+    /// The udev database is read from the `data` directory inside it.
+    pub fn udev_run_dir(mut self, path: Path) -> UdevBuilder {
+        self.udev_run_dir = path;
+        self
+    }
+
+    /// Create a udev context reading from these paths.
+    ///
+    /// With the default paths, the context uses libudev if the `libudev` feature is enabled.
+    /// Otherwise (or if any path has been changed), it uses the pure-Rust backend.
+    ///
+    /// # Error
+    ///
+    /// libudev stopped honouring `SYSFS_PATH` and `udev.conf`'s `udev_root` long ago and has no
+    /// other way to relocate these paths, so without the `sysfs` feature this fails with
+    /// `EOPNOTSUPP` if any path has been changed from its default (rather than silently reading
+    /// the real devices). It also fails if libudev can't create a context (see `Udev::try_new`).
+    pub fn build(&self) -> Result<Udev, Error> {
+        context(self).map(udev::from_context)
+    }
+
+    /// Create a factory for contexts reading from these paths (see `UdevFactory`).
+    pub fn factory(&self) -> UdevFactory {
+        udev::factory(self.clone())
+    }
+
+    // The first path that isn't the default, if any.
+    #[cfg(feature = "libudev")]
+    fn changed_path(&self) -> Option<&Path> {
+        let defaults = UdevBuilder::new();
+        if self.sysfs_root != defaults.sysfs_root {
+            Some(&self.sysfs_root)
+        } else if self.dev_root != defaults.dev_root {
+            Some(&self.dev_root)
+        } else if self.udev_run_dir != defaults.udev_run_dir {
+            Some(&self.udev_run_dir)
+        } else {
+            None
+        }
+    }
+
+    #[cfg(feature = "sysfs")]
+    fn sysfs(&self) -> Sysfs {
+        Sysfs::with_roots(self.sysfs_root.clone(), self.dev_root.clone(), self.udev_run_dir.clone())
+    }
+}
+
+#[cfg(all(feature = "libudev", feature = "sysfs"))]
+fn context(builder: &UdevBuilder) -> Result<Context, Error> {
+    match builder.changed_path() {
+        Some(_) => Ok(Context::Sysfs(builder.sysfs())),
+        None => udev::libudev_context()
+    }
+}
+
+#[cfg(all(feature = "libudev", not(feature = "sysfs")))]
+fn context(builder: &UdevBuilder) -> Result<Context, Error> {
+    match builder.changed_path() {
+        Some(path) => Err(error::os(EOPNOTSUPP, "udev_new", Some(path.display().to_string().as_slice()))),
+        None => udev::libudev_context()
+    }
+}
+
+#[cfg(not(feature = "libudev"))]
+fn context(builder: &UdevBuilder) -> Result<Context, Error> {
+    Ok(Context::Sysfs(builder.sysfs()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::UdevBuilder;
+
+    #[test]
+    #[cfg(not(feature = "sysfs"))]
+    fn test_libudev_rejects_roots() {
+        use libc::EOPNOTSUPP;
+
+        let err = UdevBuilder::new().sysfs_root(Path::new("/tmp/sys")).build().err().unwrap();
+        assert_eq!(err.errno(), Some(EOPNOTSUPP));
+        assert_eq!(err.object(), Some("/tmp/sys"));
+    }
+
+    #[test]
+    #[cfg(feature = "sysfs")]
+    fn test_roots() {
+        use std::io::TempDir;
+        use std::io::fs;
+        use std::io::USER_RWX;
+
+        let dir = TempDir::new("udev-rs-builder").unwrap();
+        fs::mkdir_recursive(&dir.path().join("sys/devices"), USER_RWX).unwrap();
+        let factory = UdevBuilder::new()
+            .sysfs_root(dir.path().join("sys"))
+            .dev_root(dir.path().join("dev"))
+            .udev_run_dir(dir.path().join("run"))
+            .factory();
+
+        // Contexts (including those created by the factory) only see devices under the roots.
+        let udev = factory.create().unwrap();
+        assert!(udev.device(&Path::new("/sys/devices/virtual/tty/tty0")).is_none());
+        let enumerator = udev.enumerator().and_then(|e| e.scan_devices()).unwrap();
+        assert_eq!(enumerator.iter_paths().count(), 0);
+        assert!(!udev.queue().unwrap().is_active());
+    }
+}
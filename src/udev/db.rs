@@ -0,0 +1,220 @@
+// This file is part of udev-rs.
+// 
+// Copyright 2014 Steven Allen <steven@stebalien.com>
+// 
+// udev-rs is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation; either version 2.1 of the License, or
+// (at your option) any later version.
+// 
+// udev-rs is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+// 
+// You should have received a copy of the GNU Lesser General Public License
+// along with udev-rs; If not, see <http://www.gnu.org/licenses/>.
+
+use std::str;
+use std::str::FromStr;
+use std::io::File;
+
+use udev::util;
+use udev::error::Error;
+
+/// A udev database record.
+///
+/// udev stores what it knows about each device in `/run/udev/data/<device id>` (see
+/// `Udev::device_from_device_id` for the ids). Each line of a record is a one letter key, a
+/// colon, and a value:
+///
+/// * `S:` a devlink (relative to `/dev`)
+/// * `L:` the devlink priority
+/// * `W:` the inotify watch handle
+/// * `I:` when the device was initialized (microseconds on the monotonic clock)
+/// * `E:` a property (`KEY=VALUE`)
+/// * `G:` a tag
+/// * `Q:` a current tag (a tag set by the latest event)
+///
+/// Other lines (e.g. `V:`, the database version) are kept as-is. Malformed lines are skipped,
+/// like udev does.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub struct Record {
+    /// Devlinks (`S:`), relative to `/dev`.
+    pub devlinks: Vec<Vec<u8>>,
+    /// The devlink priority (`L:`).
+    pub devlink_priority: Option<i32>,
+    /// The inotify watch handle (`W:`).
+    pub watch_handle: Option<i32>,
+    /// When the device was initialized (`I:`).
+    pub usec_initialized: Option<u64>,
+    /// Properties (`E:`) as (name, value) pairs.
+    pub properties: Vec<(Vec<u8>, Vec<u8>)>,
+    /// Tags (`G:`).
+    pub tags: Vec<Vec<u8>>,
+    /// Current tags (`Q:`).
+    pub current_tags: Vec<Vec<u8>>,
+    /// Lines with other keys as (key, value) pairs.
+    pub other: Vec<(u8, Vec<u8>)>,
+}
+
+impl Record {
+    /// Create an empty record.
+    pub fn new() -> Record {
+        Record {
+            devlinks: Vec::new(),
+            devlink_priority: None,
+            watch_handle: None,
+            usec_initialized: None,
+            properties: Vec::new(),
+            tags: Vec::new(),
+            current_tags: Vec::new(),
+            other: Vec::new(),
+        }
+    }
+
+    /// Parse a record.
+    pub fn parse(data: &[u8]) -> Record {
+        let mut record = Record::new();
+        for line in data.split(|&b| b == b'\n') {
+            if line.len() < 2 || line[1] != b':' {
+                continue;
+            }
+            let value = line.slice_from(2);
+            match line[0] {
+                b'S' => record.devlinks.push(value.to_vec()),
+                b'L' => if let Some(n) = parse_num(value) {
+                    record.devlink_priority = Some(n);
+                },
+                b'W' => if let Some(n) = parse_num(value) {
+                    record.watch_handle = Some(n);
+                },
+                b'I' => if let Some(n) = parse_num(value) {
+                    record.usec_initialized = Some(n);
+                },
+                b'E' => if let Some(i) = value.iter().position(|&b| b == b'=') {
+                    record.properties.push((value.slice_to(i).to_vec(), value.slice_from(i + 1).to_vec()));
+                },
+                b'G' => record.tags.push(value.to_vec()),
+                b'Q' => record.current_tags.push(value.to_vec()),
+                key => record.other.push((key, value.to_vec())),
+            }
+        }
+        record
+    }
+
+    /// Read a record from a file.
+    pub fn read(path: &Path) -> Result<Record, Error> {
+        File::open(path).read_to_end()
+            .map(|data| Record::parse(data.as_slice()))
+            .map_err(|e| util::io_error(&e, "read", path))
+    }
+
+    /// Serialize this record in the order udev writes it.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for link in self.devlinks.iter() {
+            push_line(&mut out, b'S', link.as_slice());
+        }
+        if let Some(n) = self.devlink_priority {
+            push_line(&mut out, b'L', n.to_string().as_bytes());
+        }
+        if let Some(n) = self.watch_handle {
+            push_line(&mut out, b'W', n.to_string().as_bytes());
+        }
+        if let Some(n) = self.usec_initialized {
+            push_line(&mut out, b'I', n.to_string().as_bytes());
+        }
+        for &(ref name, ref value) in self.properties.iter() {
+            let mut property = name.clone();
+            property.push(b'=');
+            property.push_all(value.as_slice());
+            push_line(&mut out, b'E', property.as_slice());
+        }
+        for tag in self.tags.iter() {
+            push_line(&mut out, b'G', tag.as_slice());
+        }
+        for tag in self.current_tags.iter() {
+            push_line(&mut out, b'Q', tag.as_slice());
+        }
+        for &(key, ref value) in self.other.iter() {
+            push_line(&mut out, key, value.as_slice());
+        }
+        out
+    }
+
+    /// Look up a property.
+    pub fn property_value_bytes(&self, name: &str) -> Option<&[u8]> {
+        self.properties.iter()
+            .find(|&&(ref key, _)| key.as_slice() == name.as_bytes())
+            .map(|&(_, ref value)| value.as_slice())
+    }
+}
+
+fn push_line(out: &mut Vec<u8>, key: u8, value: &[u8]) {
+    out.push(key);
+    out.push(b':');
+    out.push_all(value);
+    out.push(b'\n');
+}
+
+fn parse_num<T: FromStr>(value: &[u8]) -> Option<T> {
+    str::from_utf8(value).and_then(|value| from_str(value.trim()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::Record;
+
+    // As written by systemd-udevd for a partition.
+    const SAMPLE: &'static [u8] = b"S:disk/by-uuid/1234-ABCD\n\
+S:disk/by-partuuid/0001-02\n\
+L:-100\n\
+W:12\n\
+I:5327193\n\
+E:ID_FS_TYPE=vfat\n\
+E:ID_FS_LABEL=EFI=BOOT\n\
+E:ID_PART_ENTRY_NAME=\n\
+G:systemd\n\
+Q:systemd\n\
+V:1\n";
+
+    #[test]
+    fn test_parse() {
+        let record = Record::parse(SAMPLE);
+        assert_eq!(record.devlinks, vec![b"disk/by-uuid/1234-ABCD".to_vec(), b"disk/by-partuuid/0001-02".to_vec()]);
+        assert_eq!(record.devlink_priority, Some(-100));
+        assert_eq!(record.watch_handle, Some(12));
+        assert_eq!(record.usec_initialized, Some(5327193));
+        assert_eq!(record.property_value_bytes("ID_FS_TYPE"), Some(b"vfat"));
+        // Only the first `=` separates the name from the value.
+        assert_eq!(record.property_value_bytes("ID_FS_LABEL"), Some(b"EFI=BOOT"));
+        assert_eq!(record.property_value_bytes("ID_PART_ENTRY_NAME"), Some(b""));
+        assert_eq!(record.tags, vec![b"systemd".to_vec()]);
+        assert_eq!(record.current_tags, vec![b"systemd".to_vec()]);
+        assert_eq!(record.other, vec![(b'V', b"1".to_vec())]);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        assert_eq!(Record::parse(SAMPLE).to_bytes().as_slice(), SAMPLE);
+
+        let mut record = Record::new();
+        record.usec_initialized = Some(1);
+        record.properties.push((b"A".to_vec(), b"b c".to_vec()));
+        record.tags.push(b"uaccess".to_vec());
+        assert_eq!(record.to_bytes().as_slice(), b"I:1\nE:A=b c\nG:uaccess\n");
+        assert_eq!(Record::parse(record.to_bytes().as_slice()), record);
+
+        assert_eq!(Record::new().to_bytes(), Vec::new());
+    }
+
+    #[test]
+    fn test_malformed() {
+        let record = Record::parse(b"\nS\nI:x\nE:NOVALUE\nX-broken\nG:ok\n");
+        assert_eq!(record.usec_initialized, None);
+        assert!(record.properties.is_empty());
+        assert_eq!(record.tags, vec![b"ok".to_vec()]);
+        assert!(record.other.is_empty());
+    }
+}
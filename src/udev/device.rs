@@ -14,145 +14,333 @@
 // 
 // You should have received a copy of the GNU Lesser General Public License
 // along with udev-rs; If not, see <http://www.gnu.org/licenses/>.
-
+use std::str;
+#[cfg(feature = "libudev")]
 use std::ptr;
-use std::io::{IoError, standard_error, FileNotFound};
 use std::fmt;
+use std::hash;
+#[cfg(feature = "sysfs")]
+use std::rc::Rc;
 use std::time::Duration;
 
-use libc::dev_t;
+#[cfg(feature = "libudev")]
+use libc::{ENOENT, EILSEQ};
+#[cfg(not(feature = "libudev"))]
+use libc::EILSEQ;
 
 use udev::{
-    libudev_c,
     util,
     iterator,
+    error,
+    trigger,
 };
+#[cfg(feature = "libudev")]
+use udev::libudev_c;
+use udev::error::Error;
+use udev::event::Action;
+use udev::trigger::Trigger;
+use udev::attribute::FromAttribute;
 use udev::udev::Udev;
-use udev::iterator::MappedIterator;
+use udev::snapshot;
+use udev::snapshot::DeviceSnapshot;
+#[cfg(feature = "sysfs")]
+use udev::sysfs::SysfsDevice;
+use udev::iterator::{
+    UdevIterator,
+    MappedIterator,
+    FilterMappedIterator,
+};
 
-pub struct Device<'u> {
-    udev: &'u Udev,
-    dev: libudev_c::udev_device,
+pub use udev::devnum::{Devnum, Type};
+
+/// A udev device.
+///
+/// Devices are reference counted: cloning a device is cheap and the clone refers to the same
+/// underlying device. Devices hold a handle to their udev context so they can outlive the
+/// enumerator or monitor that produced them.
+///
+/// With the pure-Rust backend, a device's properties are read when it's created and its sysfs
+/// attributes are read (and cached) the first time they're asked for, like libudev does.
+pub struct Device {
+    udev: Udev,
+    dev: Inner,
 }
 
+enum Inner {
+    #[cfg(feature = "libudev")]
+    Libudev(libudev_c::udev_device),
+    #[cfg(feature = "sysfs")]
+    Sysfs(Rc<SysfsDevice>),
+}
+
+// The lists of names (and values) a device has.
+enum List {
+    Devlinks,
+    Tags,
+    Properties,
+    Attributes,
+}
+
+#[doc(hidden)]
+pub type TagIterator<'p> = FilterMappedIterator<'p, Device, &'p str>;
+#[doc(hidden)]
+pub type TagBytesIterator<'p> = MappedIterator<'p, Device, &'p [u8]>;
+#[doc(hidden)]
+pub type AttributeIterator<'p> = FilterMappedIterator<'p, Device, &'p str>;
 #[doc(hidden)]
-pub type TagIterator<'p> = MappedIterator<'p, Device<'p>, &'p str>;
+pub type AttributeBytesIterator<'p> = MappedIterator<'p, Device, &'p [u8]>;
 #[doc(hidden)]
-pub type AttributeIterator<'p> = MappedIterator<'p, Device<'p>, &'p str>;
+pub type DevlinkIterator<'p> = MappedIterator<'p, Device, Path>;
 #[doc(hidden)]
-pub type DevlinkIterator<'p> = MappedIterator<'p, Device<'p>, Path>;
+pub type PropertyIterator<'p> = FilterMappedIterator<'p, Device, (&'p str, Option<&'p str>)>;
 #[doc(hidden)]
-pub type PropertyIterator<'p> = MappedIterator<'p, Device<'p>, (&'p str, Option<&'p str>)>;
+pub type PropertyBytesIterator<'p> = MappedIterator<'p, Device, (&'p [u8], Option<&'p [u8]>)>;
 
-pub type Devnum = dev_t;
-pub enum Type {
-    Char,
-    Block
+// Crate Private
+#[cfg(feature = "libudev")]
+pub unsafe fn device(udev: &Udev, dev: libudev_c::udev_device) -> Device {
+    Device { udev: udev.clone(), dev: Inner::Libudev(dev) }
 }
 
-// Crate Private
-pub unsafe fn device<'u>(udev: &'u Udev, dev: libudev_c::udev_device) -> Device<'u> {
-    Device { udev: udev, dev: dev }
+#[cfg(feature = "sysfs")]
+pub fn sysfs_device(udev: &Udev, dev: SysfsDevice) -> Device {
+    Device { udev: udev.clone(), dev: Inner::Sysfs(Rc::new(dev)) }
 }
 
-pub unsafe fn device_get_dev(device: &Device) -> libudev_c::udev_device {
-    device.dev
+// Returns `None` for devices of the pure-Rust backend.
+#[cfg(feature = "libudev")]
+pub unsafe fn device_get_dev(device: &Device) -> Option<libudev_c::udev_device> {
+    match device.dev {
+        Inner::Libudev(dev) => Some(dev),
+        #[cfg(feature = "sysfs")]
+        Inner::Sysfs(_) => None
+    }
 }
 
-impl<'u> Device<'u> {
+impl Device {
     /// Get the udev context.
     pub fn udev(&self) -> &Udev {
-        self.udev
+        &self.udev
     }
 
     /// Get the device's parent if one exists.
     pub fn parent(&self) -> Option<Device> {
-        match util::check_errno(|| unsafe {
-            libudev_c::udev_device_ref(libudev_c::udev_device_get_parent(self.dev))
-        }) {
-            Ok(Some(dev)) => Some(unsafe { device(self.udev, dev) }),
-            _ => None
+        match self.dev {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(dev) => match util::check_errno(|| unsafe {
+                libudev_c::udev_device_ref(libudev_c::udev_device_get_parent(dev))
+            }) {
+                Ok(Some(dev)) => Some(unsafe { device(&self.udev, dev) }),
+                _ => None
+            },
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs(ref dev) => dev.parent().map(|parent| sysfs_device(&self.udev, parent))
         }
     }
 
     /// Get the first parent with the specified subsystem.
     pub fn parent_with_subsystem(&self, subsystem: &str) -> Option<Device> {
-        match subsystem.with_c_str(|subsystem| util::check_errno(|| unsafe {
-            libudev_c::udev_device_ref(
-                libudev_c::udev_device_get_parent_with_subsystem_devtype(self.dev, subsystem, ptr::null()))
-        })) {
-            Ok(Some(dev)) => Some(unsafe { device(self.udev, dev) }),
-            _ => None
+        match self.dev {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(dev) => match subsystem.with_c_str(|subsystem| util::check_errno(|| unsafe {
+                libudev_c::udev_device_ref(
+                    libudev_c::udev_device_get_parent_with_subsystem_devtype(dev, subsystem, ptr::null()))
+            })) {
+                Ok(Some(dev)) => Some(unsafe { device(&self.udev, dev) }),
+                _ => None
+            },
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs(_) => self.find_parent(subsystem, None)
         }
     }
 
     /// Get the first parent with the specified subsystem and devtype.
     pub fn parent_with_subsystem_devtype(&self, subsystem: &str, devtype: &str) -> Option<Device> {
-        match subsystem.with_c_str(|subsystem| devtype.with_c_str(|devtype| util::check_errno(|| unsafe {
-            libudev_c::udev_device_ref(
-                libudev_c::udev_device_get_parent_with_subsystem_devtype(self.dev, subsystem, devtype))
-        }))) {
-            Ok(Some(dev)) => Some(unsafe { device(self.udev, dev) }),
-            _ => None
+        match self.dev {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(dev) => match subsystem.with_c_str(|subsystem| devtype.with_c_str(|devtype| {
+                util::check_errno(|| unsafe {
+                    libudev_c::udev_device_ref(
+                        libudev_c::udev_device_get_parent_with_subsystem_devtype(dev, subsystem, devtype))
+                })
+            })) {
+                Ok(Some(dev)) => Some(unsafe { device(&self.udev, dev) }),
+                _ => None
+            },
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs(_) => self.find_parent(subsystem, Some(devtype))
         }
     }
 
     /// Read a sysfs attribute.
-    pub fn attribute<'s>(&'s self, attr: &str) -> Result<&'s str, IoError> {
-        match attr.with_c_str(|cstr| util::check_errno(|| unsafe {
-            libudev_c::udev_device_get_sysattr_value(self.dev, cstr)
-        })) {
-            Ok(Some(val)) => Ok(unsafe { util::c_to_str(val) }.unwrap()),
-            Ok(None) => Err(standard_error(FileNotFound)),
-            Err(errno) => Err(IoError::from_errno(errno as uint, true)),
+    ///
+    /// # Error
+    ///
+    /// Returns an `EILSEQ` error if the attribute's value isn't valid UTF-8. Use
+    /// `attribute_bytes` or `attribute_lossy` to read such attributes.
+    pub fn attribute<'s>(&'s self, attr: &str) -> Result<&'s str, Error> {
+        match str::from_utf8(try!(self.attribute_bytes(attr))) {
+            Some(value) => Ok(value),
+            None => Err(error::os(EILSEQ, "udev_device_get_sysattr_value", Some(attr))),
+        }
+    }
+
+    /// Read a sysfs attribute as raw bytes.
+    pub fn attribute_bytes<'s>(&'s self, attr: &str) -> Result<&'s [u8], Error> {
+        match self.dev {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(dev) => match attr.with_c_str(|cstr| util::check_errno(|| unsafe {
+                libudev_c::udev_device_get_sysattr_value(dev, cstr)
+            })) {
+                Ok(Some(val)) => Ok(unsafe { util::c_to_bytes(val) }.unwrap()),
+                Ok(None) => Err(error::os(ENOENT, "udev_device_get_sysattr_value", Some(attr))),
+                Err(errno) => Err(error::os(errno, "udev_device_get_sysattr_value", Some(attr))),
+            },
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs(ref dev) => dev.attribute_bytes(attr)
+        }
+    }
+
+    /// Read a sysfs attribute, replacing invalid UTF-8 sequences.
+    pub fn attribute_lossy(&self, attr: &str) -> Result<String, Error> {
+        self.attribute_bytes(attr).map(util::lossy)
+    }
+
+    /// Read and parse a sysfs attribute.
+    ///
+    /// Surrounding whitespace (e.g. the trailing newline) is stripped before parsing. See
+    /// `FromAttribute` for the supported types.
+    ///
+    /// ```ignore
+    /// let size = try!(dev.attribute_as::<u64>("size"));
+    /// let Hex(vendor) = try!(dev.attribute_as::<Hex>("idVendor"));
+    /// let scheduler = try!(dev.attribute_as::<Selection>("queue/scheduler"));
+    /// ```
+    ///
+    /// # Error
+    ///
+    /// Returns an `Error::Parse` naming the attribute and the device if the value can't be parsed.
+    pub fn attribute_as<T: FromAttribute>(&self, attr: &str) -> Result<T, Error> {
+        let value = try!(self.attribute(attr));
+        match FromAttribute::from_attribute(value.trim()) {
+            Some(parsed) => Ok(parsed),
+            None => Err(error::parse(attr, &self.syspath(), value.trim()))
         }
     }
 
     /// Write a sysfs attribute.
-    pub fn set_attribute(&self, attr: &str, value: &str) -> Result<(), IoError> {
-        attr.with_c_str(|attr| value.with_c_str(|value| match unsafe {
-            libudev_c::udev_device_set_sysattr_value(self.dev, attr, value)
-        } {
-            0           => Ok(()),
-            n if n < 0  => Err(IoError::from_errno(-n as uint, true)),
-            _           => panic!("udev returned an invalid error")
-        }))
+    pub fn set_attribute(&self, attr: &str, value: &str) -> Result<(), Error> {
+        match self.dev {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(dev) => attr.with_c_str(|c_attr| value.with_c_str(|c_value| util::handle_error(unsafe {
+                libudev_c::udev_device_set_sysattr_value(dev, c_attr, c_value)
+            }, "udev_device_set_sysattr_value", Some(attr)))),
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs(ref dev) => dev.set_attribute(attr, value)
+        }
     }
 
     /// Get the path to the device (minus `/sys`).
-    pub fn devpath<'s>(&'s self) -> &'s str {
-        unsafe {
-            util::c_to_str(libudev_c::udev_device_get_devpath(self.dev)).unwrap()
+    ///
+    /// Returns `None` if the path isn't valid UTF-8.
+    pub fn devpath<'s>(&'s self) -> Option<&'s str> {
+        str::from_utf8(self.devpath_bytes())
+    }
+
+    /// Get the path to the device (minus `/sys`) as raw bytes.
+    pub fn devpath_bytes<'s>(&'s self) -> &'s [u8] {
+        match self.dev {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(dev) => unsafe {
+                // Every device has a devpath.
+                util::c_to_bytes(libudev_c::udev_device_get_devpath(dev)).unwrap()
+            },
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs(ref dev) => dev.snapshot().devpath_bytes()
         }
     }
 
+    /// Get the path to the device (minus `/sys`), replacing invalid UTF-8 sequences.
+    pub fn devpath_lossy(&self) -> String {
+        util::lossy(self.devpath_bytes())
+    }
+
     /// Get the full path to the device (including `/sys`).
     pub fn syspath<'s>(&'s self) -> Path {
-        unsafe {
-            Path::new(util::c_to_str(libudev_c::udev_device_get_syspath(self.dev)).unwrap())
+        Path::new(self.syspath_bytes())
+    }
+
+    /// Get the full path to the device (including `/sys`) as raw bytes.
+    pub fn syspath_bytes<'s>(&'s self) -> &'s [u8] {
+        match self.dev {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(dev) => unsafe {
+                // Every device has a syspath.
+                util::c_to_bytes(libudev_c::udev_device_get_syspath(dev)).unwrap()
+            },
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs(ref dev) => dev.snapshot().syspath().as_vec()
         }
     }
 
     /// Get the device name.
     ///
     /// E.g. wlan0
-    pub fn sysname<'s>(&'s self) -> &'s str {
-        unsafe {
-            util::c_to_str(libudev_c::udev_device_get_sysname(self.dev)).unwrap()
+    ///
+    /// Returns `None` if the name isn't valid UTF-8.
+    pub fn sysname<'s>(&'s self) -> Option<&'s str> {
+        str::from_utf8(self.sysname_bytes())
+    }
+
+    /// Get the device name as raw bytes.
+    pub fn sysname_bytes<'s>(&'s self) -> &'s [u8] {
+        match self.dev {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(dev) => unsafe {
+                // Every device has a sysname.
+                util::c_to_bytes(libudev_c::udev_device_get_sysname(dev)).unwrap()
+            },
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs(ref dev) => dev.snapshot().sysname_bytes()
         }
     }
 
+    /// Get the device name, replacing invalid UTF-8 sequences.
+    pub fn sysname_lossy(&self) -> String {
+        util::lossy(self.sysname_bytes())
+    }
+
     /// Get the devices subsystem
     pub fn subsystem<'s>(&'s self) -> Option<&'s str> {
-        unsafe {
-            util::c_to_str(libudev_c::udev_device_get_subsystem(self.dev))
+        self.subsystem_bytes().and_then(str::from_utf8)
+    }
+
+    /// Get the devices subsystem as raw bytes.
+    pub fn subsystem_bytes<'s>(&'s self) -> Option<&'s [u8]> {
+        match self.dev {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(dev) => unsafe {
+                util::c_to_bytes(libudev_c::udev_device_get_subsystem(dev))
+            },
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs(ref dev) => dev.snapshot().subsystem_bytes()
         }
     }
 
     /// Get the devices devtype
     pub fn devtype<'s>(&'s self) -> Option<&'s str> {
-        unsafe {
-            util::c_to_str(libudev_c::udev_device_get_devtype(self.dev))
+        self.devtype_bytes().and_then(str::from_utf8)
+    }
+
+    /// Get the devices devtype as raw bytes.
+    pub fn devtype_bytes<'s>(&'s self) -> Option<&'s [u8]> {
+        match self.dev {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(dev) => unsafe {
+                util::c_to_bytes(libudev_c::udev_device_get_devtype(dev))
+            },
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs(ref dev) => dev.snapshot().devtype_bytes()
         }
     }
 
@@ -160,28 +348,58 @@ impl<'u> Device<'u> {
     ///
     /// E.g. the X in ethX, wlanX, etc.
     pub fn sysnum(&self) -> Option<u64> {
-        match unsafe {
-            util::c_to_str(libudev_c::udev_device_get_sysnum(self.dev))
-        } {
-            Some(n) => from_str(n),
-            None => None
+        match self.dev {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(dev) => unsafe {
+                util::c_to_str(libudev_c::udev_device_get_sysnum(dev))
+            }.and_then(from_str),
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs(ref dev) => dev.sysnum()
         }
     }
 
     /// Get the device's devnum.
     pub fn devnum(&self) -> Option<Devnum> {
-        match unsafe {
-            libudev_c::udev_device_get_devnum(self.dev)
-        } {
-            0 => None,
-            n => Some(n)
+        match self.dev {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(dev) => match unsafe {
+                libudev_c::udev_device_get_devnum(dev)
+            } {
+                0 => None,
+                n => Some(Devnum::from_raw(n))
+            },
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs(ref dev) => dev.snapshot().devnum()
+        }
+    }
+
+    /// Get the device's type (character or block).
+    ///
+    /// Returns `None` if the device doesn't have a device node.
+    pub fn device_type(&self) -> Option<Type> {
+        if self.devnum().is_none() && self.devnode().is_none() {
+            None
+        } else if self.subsystem_bytes() == Some(b"block") {
+            Some(Type::Block)
+        } else {
+            Some(Type::Char)
         }
     }
 
     /// Get the device's driver.
     pub fn driver(&self) -> Option<&str> {
-        unsafe {
-            util::c_to_str(libudev_c::udev_device_get_driver(self.dev))
+        self.driver_bytes().and_then(str::from_utf8)
+    }
+
+    /// Get the device's driver as raw bytes.
+    pub fn driver_bytes(&self) -> Option<&[u8]> {
+        match self.dev {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(dev) => unsafe {
+                util::c_to_bytes(libudev_c::udev_device_get_driver(dev))
+            },
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs(ref dev) => dev.snapshot().driver_bytes()
         }
     }
 
@@ -189,83 +407,330 @@ impl<'u> Device<'u> {
     ///
     /// E.g. `/dev/sda`
     pub fn devnode(& self) -> Option<Path> {
-        unsafe {
-            util::c_to_str(libudev_c::udev_device_get_devnode(self.dev))
-        }.map(|path| Path::new(path))
+        match self.dev {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(dev) => unsafe {
+                util::c_to_bytes(libudev_c::udev_device_get_devnode(dev))
+            }.map(|path| Path::new(path)),
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs(ref dev) => dev.snapshot().devnode().map(|path| path.clone())
+        }
     }
 
     /// Iterate over the device's devlinks
     ///
     /// E.g. the symlinks in `/dev/disk/by-*/`
     pub fn iter_devlinks(&self) -> DevlinkIterator {
-        unsafe {
-            iterator::iterator(self, libudev_c::udev_device_get_devlinks_list_entry(self.dev))
-        }.map(|(_, key, _)| Path::new(key))
+        self.list(List::Devlinks).map(|(_, key, _)| Path::new(key))
     }
 
     /// Iterate over the device's tags.
+    ///
+    /// Tags that aren't valid UTF-8 are skipped (see `iter_tags_bytes`).
     pub fn iter_tags(&self) -> TagIterator {
-        unsafe {
-            iterator::iterator(self, libudev_c::udev_device_get_tags_list_entry(self.dev))
-        }.map(|(_, key, _)| key)
+        self.list(List::Tags).filter_map(|(_, key, _)| str::from_utf8(key))
+    }
+
+    /// Iterate over the device's tags as raw bytes.
+    pub fn iter_tags_bytes(&self) -> TagBytesIterator {
+        self.list(List::Tags).map(|(_, key, _)| key)
     }
 
     /// Iterate over the device's properties.
+    ///
+    /// Properties with a name or value that isn't valid UTF-8 are skipped (see
+    /// `iter_properties_bytes`).
     pub fn iter_properties(&self) -> PropertyIterator {
-        unsafe {
-            iterator::iterator(self, libudev_c::udev_device_get_properties_list_entry(self.dev))
-        }.map(|(_, key, value)| (key, value))
+        self.list(List::Properties).filter_map(|(_, key, value)| match (str::from_utf8(key), value) {
+            (Some(key), None) => Some((key, None)),
+            (Some(key), Some(value)) => str::from_utf8(value).map(|value| (key, Some(value))),
+            (None, _) => None,
+        })
+    }
+
+    /// Iterate over the device's properties as raw bytes.
+    pub fn iter_properties_bytes(&self) -> PropertyBytesIterator {
+        self.list(List::Properties).map(|(_, key, value)| (key, value))
     }
 
+    /// Look up a property.
+    ///
+    /// E.g. `ID_MODEL`, `DEVNAME`, `ID_FS_UUID`
+    ///
+    /// Returns `None` if the property doesn't exist or isn't valid UTF-8.
+    pub fn property_value<'s>(&'s self, name: &str) -> Option<&'s str> {
+        self.property_value_bytes(name).and_then(str::from_utf8)
+    }
+
+    /// Look up a property as raw bytes.
+    pub fn property_value_bytes<'s>(&'s self, name: &str) -> Option<&'s [u8]> {
+        match self.dev {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(dev) => name.with_c_str(|name| unsafe {
+                util::c_to_bytes(libudev_c::udev_device_get_property_value(dev, name))
+            }),
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs(ref dev) => dev.snapshot().property_value_bytes(name)
+        }
+    }
+    /// Look up a boolean property (`1` or `0`).
+    ///
+    /// Returns `None` if the property doesn't exist or isn't a boolean.
+    pub fn property_bool(&self, name: &str) -> Option<bool> {
+        self.property_value(name).and_then(util::parse_bool)
+    }
+
+    /// Look up a decimal integer property.
+    ///
+    /// Returns `None` if the property doesn't exist or isn't an integer.
+    pub fn property_u64(&self, name: &str) -> Option<u64> {
+        self.property_value(name).and_then(|value| from_str(value.trim()))
+    }
+
+    /// Look up a hexadecimal integer property (with or without a `0x` prefix).
+    ///
+    /// E.g. `ID_VENDOR_ID`
+    ///
+    /// Returns `None` if the property doesn't exist or isn't a hexadecimal integer.
+    pub fn property_hex(&self, name: &str) -> Option<u64> {
+        self.property_value(name).and_then(util::parse_hex)
+    }
+
+    /// Look up a space separated list property.
+    ///
+    /// E.g. `DEVLINKS`
+    ///
+    /// Returns an empty list if the property doesn't exist.
+    pub fn property_list<'s>(&'s self, name: &str) -> Vec<&'s str> {
+        match self.property_value(name) {
+            Some(value) => value.words().collect(),
+            None => Vec::new()
+        }
+    }
+
+
     /// Iterate over the device's sysfs attribute names
+    ///
+    /// Names that aren't valid UTF-8 are skipped (see `iter_attributes_bytes`).
     pub fn iter_attributes(& self) -> AttributeIterator {
-        unsafe {
-            iterator::iterator(self, libudev_c::udev_device_get_sysattr_list_entry(self.dev))
-        }.map(|(_, key, _)| key)
+        self.list(List::Attributes).filter_map(|(_, key, _)| str::from_utf8(key))
+    }
+
+    /// Iterate over the device's sysfs attribute names as raw bytes.
+    pub fn iter_attributes_bytes(& self) -> AttributeBytesIterator {
+        self.list(List::Attributes).map(|(_, key, _)| key)
     }
 
     /// Get the time since the device was initialized by udev.
     pub fn time_since_initialized(&self) -> Option<Duration> {
-        let usec = unsafe { libudev_c::udev_device_get_usec_since_initialized(self.dev) };
-        if usec == 0 {
-            None
-        } else {
-            // Note: I don't support machines that are online for over 292,471 years. Sorry.
-            Some(Duration::microseconds(usec as i64))
+        match self.dev {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(dev) => {
+                let usec = unsafe { libudev_c::udev_device_get_usec_since_initialized(dev) };
+                if usec == 0 {
+                    None
+                } else {
+                    // Note: I don't support machines that are online for over 292,471 years. Sorry.
+                    Some(Duration::microseconds(usec as i64))
+                }
+            },
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs(ref dev) => dev.time_since_initialized()
         }
     }
 
     /// Determine if the device has been initialized.
     pub fn is_initialized(&self) -> bool {
-        unsafe { libudev_c::udev_device_get_is_initialized(self.dev) != 0 }
+        match self.dev {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(dev) => unsafe { libudev_c::udev_device_get_is_initialized(dev) != 0 },
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs(ref dev) => dev.snapshot().is_initialized()
+        }
+    }
+    /// Get an object for printing everything udev knows about this device.
+    ///
+    /// The output is similar to `udevadm info`:
+    ///
+    /// ```text
+    /// P: /devices/pci0000:00/0000:00:1f.2/ata1/host0/target0:0:0/0:0:0:0/block/sda
+    /// N: /dev/sda
+    /// S: /dev/disk/by-id/ata-...
+    /// E: DEVTYPE=disk
+    /// E: SUBSYSTEM=block
+    /// ...
+    /// ```
+    pub fn info<'s>(&'s self) -> Info<'s> {
+        Info { device: self }
+    }
+
+    /// Copy this device into a `DeviceSnapshot` that can be sent to another thread.
+    ///
+    /// The snapshot doesn't include sysfs attributes (see `snapshot_with_attributes`).
+    pub fn snapshot(&self) -> DeviceSnapshot {
+        snapshot::snapshot(self, false)
+    }
+
+    /// Copy this device into a `DeviceSnapshot`, including the values of all readable sysfs
+    /// attributes.
+    ///
+    /// This reads every attribute file of the device, which can be slow (and some values, like
+    /// `stat`, change constantly).
+    pub fn snapshot_with_attributes(&self) -> DeviceSnapshot {
+        snapshot::snapshot(self, true)
     }
 
     /// Check whether the device is tagged with a given tag.
     pub fn has_tag(&self, tag: &str) -> bool {
-        tag.with_c_str(|tag| unsafe {
-            libudev_c::udev_device_has_tag(self.dev, tag) != 0
-        })
+        match self.dev {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(dev) => tag.with_c_str(|tag| unsafe {
+                libudev_c::udev_device_has_tag(dev, tag) != 0
+            }),
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs(ref dev) => dev.snapshot().has_tag(tag)
+        }
+    }
+
+    /// Trigger a synthetic event for this device (like `udevadm trigger`).
+    ///
+    /// Requires write access to sysfs (usually root).
+    pub fn trigger(&self, action: Action) -> Result<(), Error> {
+        self.trigger_with(&Trigger::new(action))
+    }
+
+    /// Trigger a synthetic event for this device with a UUID, arguments or waiting (see
+    /// `Trigger`).
+    pub fn trigger_with(&self, t: &Trigger) -> Result<(), Error> {
+        trigger::trigger(&self.udev, vec![self.syspath()], t)
+    }
+
+    fn list<'s>(&'s self, list: List) -> UdevIterator<'s, Device> {
+        match self.dev {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(dev) => unsafe {
+                iterator::iterator(self, match list {
+                    List::Devlinks => libudev_c::udev_device_get_devlinks_list_entry(dev),
+                    List::Tags => libudev_c::udev_device_get_tags_list_entry(dev),
+                    List::Properties => libudev_c::udev_device_get_properties_list_entry(dev),
+                    List::Attributes => libudev_c::udev_device_get_sysattr_list_entry(dev),
+                })
+            },
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs(ref dev) => {
+                let snapshot = dev.snapshot();
+                iterator::owned_iterator(self, match list {
+                    List::Devlinks => snapshot.devlinks().iter().map(|link| (link.as_vec(), None)).collect(),
+                    List::Tags => snapshot.tags().iter().map(|tag| (tag.as_slice(), None)).collect(),
+                    List::Properties => snapshot.properties().iter().map(|&(ref key, ref value)| {
+                        (key.as_slice(), value.as_ref().map(|value| value.as_slice()))
+                    }).collect(),
+                    List::Attributes => dev.attribute_names().iter().map(|name| (name.as_slice(), None)).collect(),
+                })
+            }
+        }
+    }
+
+    // libudev only looks at the parents' subsystems and devtypes, which sysfs devices read
+    // up front; walk up until one matches.
+    #[cfg(feature = "sysfs")]
+    fn find_parent(&self, subsystem: &str, devtype: Option<&str>) -> Option<Device> {
+        let mut parent = self.parent();
+        loop {
+            parent = match parent {
+                Some(dev) => {
+                    if dev.subsystem() == Some(subsystem) && (devtype.is_none() || dev.devtype() == devtype) {
+                        return Some(dev);
+                    }
+                    dev.parent()
+                },
+                None => return None
+            }
+        }
     }
 }
 
-#[unsafe_destructor]
-impl<'u> Drop for Device<'u> {
+impl Clone for Device {
+    fn clone(&self) -> Device {
+        match self.dev {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(dev) => unsafe { device(&self.udev, libudev_c::udev_device_ref(dev)) },
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs(ref dev) => Device { udev: self.udev.clone(), dev: Inner::Sysfs(dev.clone()) }
+        }
+    }
+}
+
+impl Drop for Device {
     fn drop(&mut self) {
-        unsafe { libudev_c::udev_device_unref(self.dev) };
+        match self.dev {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(dev) => unsafe { libudev_c::udev_device_unref(dev); },
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs(_) => ()
+        }
     }
 }
 
-impl<'u> fmt::Show for Device<'u> {
+impl fmt::Show for Device {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.syspath().as_str().unwrap())
+        write!(f, "{}", self.syspath().display())
+    }
+}
+
+// Devices are identified by their syspath.
+impl PartialEq for Device {
+    fn eq(&self, other: &Device) -> bool {
+        self.syspath_bytes() == other.syspath_bytes()
     }
 }
 
-impl Type {
-    pub fn to_char(self) -> i8 {
-        match self {
-            Type::Char => 'c' as i8,
-            Type::Block => 'b' as i8
+impl Eq for Device {}
+
+impl PartialOrd for Device {
+    fn partial_cmp(&self, other: &Device) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Device {
+    fn cmp(&self, other: &Device) -> Ordering {
+        self.syspath_bytes().cmp(&other.syspath_bytes())
+    }
+}
+
+impl<S: hash::Writer> hash::Hash<S> for Device {
+    fn hash(&self, state: &mut S) {
+        self.syspath_bytes().hash(state)
+    }
+}
+
+/// Helper struct for printing a device's details with `format!` and `{}`.
+///
+/// See `Device::info`.
+pub struct Info<'a> {
+    device: &'a Device,
+}
+
+impl<'a> fmt::Show for Info<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let dev = self.device;
+        try!(writeln!(f, "P: {}", dev.devpath_lossy()));
+        if let Some(devnode) = dev.devnode() {
+            try!(writeln!(f, "N: {}", devnode.display()));
+        }
+        for devlink in dev.iter_devlinks() {
+            try!(writeln!(f, "S: {}", devlink.display()));
+        }
+        // udev doesn't always export the driver as a property.
+        if let Some(driver) = dev.driver_bytes() {
+            if !dev.iter_properties_bytes().any(|(key, _)| key == b"DRIVER") {
+                try!(writeln!(f, "E: DRIVER={}", util::lossy(driver)));
+            }
+        }
+        for (key, value) in dev.iter_properties_bytes() {
+            try!(writeln!(f, "E: {}={}", util::lossy(key), value.map_or(String::new(), util::lossy)));
         }
+        Ok(())
     }
 }
@@ -0,0 +1,113 @@
+// This file is part of udev-rs.
+// 
+// Copyright 2014 Steven Allen <steven@stebalien.com>
+// 
+// udev-rs is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation; either version 2.1 of the License, or
+// (at your option) any later version.
+// 
+// udev-rs is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+// 
+// You should have received a copy of the GNU Lesser General Public License
+// along with udev-rs; If not, see <http://www.gnu.org/licenses/>.
+
+use std::fmt;
+use std::str::FromStr;
+
+use libc::dev_t;
+
+/// A device number (major and minor number).
+#[deriving(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Devnum(dev_t);
+
+/// A device type (character or block).
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub enum Type {
+    Char,
+    Block
+}
+
+impl Type {
+    pub fn to_char(self) -> i8 {
+        match self {
+            Type::Char => 'c' as i8,
+            Type::Block => 'b' as i8
+        }
+    }
+}
+
+impl Devnum {
+    /// Create a devnum from a major and a minor number.
+    pub fn new(major: u32, minor: u32) -> Devnum {
+        // Same encoding as glibc's gnu_dev_makedev.
+        let (major, minor) = (major as u64, minor as u64);
+        Devnum(((minor & 0xff) | ((major & 0xfff) << 8)
+                | ((minor & !0xff) << 12) | ((major & !0xfff) << 32)) as dev_t)
+    }
+
+    /// Create a devnum from a raw `dev_t`.
+    pub fn from_raw(devnum: dev_t) -> Devnum {
+        Devnum(devnum)
+    }
+
+    /// Get the raw `dev_t`.
+    pub fn to_raw(self) -> dev_t {
+        let Devnum(devnum) = self;
+        devnum
+    }
+
+    /// Get the major number.
+    pub fn major(self) -> u32 {
+        let devnum = self.to_raw() as u64;
+        (((devnum >> 8) & 0xfff) | ((devnum >> 32) & !0xfff)) as u32
+    }
+
+    /// Get the minor number.
+    pub fn minor(self) -> u32 {
+        let devnum = self.to_raw() as u64;
+        ((devnum & 0xff) | ((devnum >> 12) & !0xff)) as u32
+    }
+}
+
+/// Parses the `major:minor` format used by sysfs `dev` files.
+impl FromStr for Devnum {
+    fn from_str(s: &str) -> Option<Devnum> {
+        let mut parts = s.trim().splitn(1, ':');
+        match (parts.next().and_then(from_str), parts.next().and_then(from_str)) {
+            (Some(major), Some(minor)) => Some(Devnum::new(major, minor)),
+            _ => None
+        }
+    }
+}
+
+/// Formats the devnum as `major:minor`.
+impl fmt::Show for Devnum {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.major(), self.minor())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Devnum;
+
+    #[test]
+    fn test_devnum() {
+        let devnum = Devnum::new(8, 1);
+        assert_eq!(devnum.to_raw(), 0x801);
+        assert_eq!((devnum.major(), devnum.minor()), (8, 1));
+
+        let big = Devnum::new(259, 300000);
+        assert_eq!((big.major(), big.minor()), (259, 300000));
+        assert_eq!(Devnum::from_raw(big.to_raw()), big);
+
+        assert_eq!(from_str::<Devnum>("8:0\n"), Some(Devnum::new(8, 0)));
+        assert_eq!(from_str::<Devnum>("8"), None);
+        assert_eq!(from_str::<Devnum>("a:b"), None);
+        assert_eq!(Devnum::new(189, 1).to_string(), "189:1".to_string());
+    }
+}
@@ -15,43 +15,74 @@
 // You should have received a copy of the GNU Lesser General Public License
 // along with udev-rs; If not, see <http://www.gnu.org/licenses/>.
 
+#[cfg(feature = "libudev")]
 use std::ptr;
 
+#[cfg(feature = "libudev")]
+use libc::{c_int, ENODEV};
+
+use udev::{
+    iterator,
+    trigger,
+};
+#[cfg(feature = "libudev")]
 use udev::{
     libudev_c,
     util,
-    iterator,
     device,
+    error,
 };
 use udev::udev::Udev;
+use udev::event::Action;
+use udev::trigger::Trigger;
 use udev::device::Device;
+use udev::error::Error;
+#[cfg(feature = "sysfs")]
+use udev::sysfs::Scan;
 use udev::iterator::{
+    UdevIterator,
     MappedIterator,
     FilterMappedIterator,
 };
 
-pub struct Enumerator<'u> {
-    udev: &'u Udev,
-    enumerator: libudev_c::udev_enumerate
+pub struct Enumerator {
+    udev: Udev,
+    enumerator: Inner,
+}
+
+enum Inner {
+    #[cfg(feature = "libudev")]
+    Libudev(libudev_c::udev_enumerate),
+    #[cfg(feature = "sysfs")]
+    Sysfs(Scan),
 }
 
 // Crate Private
-pub unsafe fn enumerator<'u>(udev: &'u Udev, enumerator: libudev_c::udev_enumerate) -> Enumerator<'u> {
+#[cfg(feature = "libudev")]
+pub unsafe fn enumerator(udev: &Udev, enumerator: libudev_c::udev_enumerate) -> Enumerator {
     Enumerator {
-        udev: udev,
-        enumerator: enumerator
+        udev: udev.clone(),
+        enumerator: Inner::Libudev(enumerator)
+    }
+}
+
+#[cfg(feature = "sysfs")]
+pub fn sysfs_enumerator(udev: &Udev, scan: Scan) -> Enumerator {
+    Enumerator {
+        udev: udev.clone(),
+        enumerator: Inner::Sysfs(scan)
     }
 }
 
 #[doc(hidden)]
-pub type DeviceIterator<'e, 'u: 'e> = FilterMappedIterator<'e, Enumerator<'u>, Device<'u>>;
+pub type DeviceIterator<'e> = FilterMappedIterator<'e, Enumerator, Device>;
 #[doc(hidden)]
-pub type DevicePathIterator<'p> = MappedIterator<'p, Enumerator<'p>, Path>;
+pub type DevicePathIterator<'p> = MappedIterator<'p, Enumerator, Path>;
 
-impl<'u> Enumerator<'u> {
+impl Enumerator {
     /// Get the udev context.
     pub fn udev(&self) -> &Udev {
-        self.udev
+        &self.udev
     }
 
     /// Include devices with the specified subsystem.
@@ -59,107 +90,170 @@ impl<'u> Enumerator<'u> {
     /// All devices added by future scans will match either the specified subsystem or a subsystem
     /// specified in a previous invocation of this function (on this enumerator). If this function
     /// has never been called, devices will not be filtered by subsystem.
-    pub fn match_subsystem(self, subsystem: &str) -> Enumerator<'u> {
-        subsystem.with_c_str(|subsystem| util::handle_error(unsafe {
-            libudev_c::udev_enumerate_add_match_subsystem(self.enumerator, subsystem)
-        }));
-        self
+    pub fn match_subsystem(mut self, subsystem: &str) -> Result<Enumerator, Error> {
+        match self.enumerator {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(e) => try!(subsystem.with_c_str(|c_subsystem| util::handle_error(unsafe {
+                libudev_c::udev_enumerate_add_match_subsystem(e, c_subsystem)
+            }, "udev_enumerate_add_match_subsystem", Some(subsystem)))),
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs(ref mut scan) => scan.filter = scan.filter.clone().match_subsystem(subsystem),
+        }
+        Ok(self)
     }
 
     /// Exclude devices with the specified subsystem.
     ///
     /// No device added by a future scan will have the specified subsystem.
-    pub fn match_not_subsystem(self, subsystem: &str) -> Enumerator<'u> {
-        subsystem.with_c_str(|subsystem| util::handle_error(unsafe {
-            libudev_c::udev_enumerate_add_nomatch_subsystem(self.enumerator, subsystem)
-        }));
-        self
+    pub fn match_not_subsystem(mut self, subsystem: &str) -> Result<Enumerator, Error> {
+        match self.enumerator {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(e) => try!(subsystem.with_c_str(|c_subsystem| util::handle_error(unsafe {
+                libudev_c::udev_enumerate_add_nomatch_subsystem(e, c_subsystem)
+            }, "udev_enumerate_add_nomatch_subsystem", Some(subsystem)))),
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs(ref mut scan) => scan.filter = scan.filter.clone().match_not_subsystem(subsystem),
+        }
+        Ok(self)
     }
 
     /// Only include devices with an attribute.
     ///
     /// All devices added by future scans will have the specified attribute with
     /// the (optionally) specified value.
-    pub fn match_attribute(self, attr: &str, value: Option<&str>) -> Enumerator<'u> {
-        fn it(e: &Enumerator, attr: *const i8, value: *const i8) {
-            util::handle_error(unsafe {libudev_c::udev_enumerate_add_match_sysattr(e.enumerator, attr, value)});
+    pub fn match_attribute(mut self, attr: &str, value: Option<&str>) -> Result<Enumerator, Error> {
+        match self.enumerator {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(e) => {
+                fn it(e: libudev_c::udev_enumerate, attr: *const i8, value: *const i8) -> c_int {
+                    unsafe { libudev_c::udev_enumerate_add_match_sysattr(e, attr, value) }
+                }
+                try!(util::handle_error(attr.with_c_str(|c_attr| match value {
+                    Some(value) => value.with_c_str(|value| it(e, c_attr, value)),
+                    None => it(e, c_attr, ptr::null())
+                }), "udev_enumerate_add_match_sysattr", Some(attr)));
+            },
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs(ref mut scan) => scan.filter = scan.filter.clone().match_attribute(attr, value),
         }
-        attr.with_c_str(|attr| match value {
-            Some(value) => value.with_c_str(|value| it(&self, attr, value)),
-            None => it(&self, attr, ptr::null())
-        });
-        self
+        Ok(self)
     }
 
     /// Exclude devices with an attribute.
     ///
     /// No device added by future scans will have the specified attribute with
     /// the (optionally) specified value.
-    pub fn match_not_attribute(self, attr: &str, value: Option<&str>) -> Enumerator<'u> {
-        fn it(e: &Enumerator, attr: *const i8, value: *const i8) {
-            util::handle_error(unsafe {libudev_c::udev_enumerate_add_nomatch_sysattr(e.enumerator, attr, value)});
+    pub fn match_not_attribute(mut self, attr: &str, value: Option<&str>) -> Result<Enumerator, Error> {
+        match self.enumerator {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(e) => {
+                fn it(e: libudev_c::udev_enumerate, attr: *const i8, value: *const i8) -> c_int {
+                    unsafe { libudev_c::udev_enumerate_add_nomatch_sysattr(e, attr, value) }
+                }
+                try!(util::handle_error(attr.with_c_str(|c_attr| match value {
+                    Some(value) => value.with_c_str(|value| it(e, c_attr, value)),
+                    None => it(e, c_attr, ptr::null())
+                }), "udev_enumerate_add_nomatch_sysattr", Some(attr)));
+            },
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs(ref mut scan) => scan.filter = scan.filter.clone().match_not_attribute(attr, value),
         }
-        attr.with_c_str(|attr| match value {
-            Some(value) => value.with_c_str(|value| it(&self, attr, value)),
-            None => it(&self, attr, ptr::null())
-        });
-        self
+        Ok(self)
     }
 
     /// Only include devices with a property.
     ///
     /// All devices added by future scans will have the specified property with
     /// the (optionally) specified value.
-    pub fn match_property(self, attr: &str, value: Option<&str>) -> Enumerator<'u> {
-        fn it(e: &Enumerator, attr: *const i8, value: *const i8) {
-            util::handle_error(unsafe {libudev_c::udev_enumerate_add_match_property(e.enumerator, attr, value)});
+    pub fn match_property(mut self, attr: &str, value: Option<&str>) -> Result<Enumerator, Error> {
+        match self.enumerator {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(e) => {
+                fn it(e: libudev_c::udev_enumerate, attr: *const i8, value: *const i8) -> c_int {
+                    unsafe { libudev_c::udev_enumerate_add_match_property(e, attr, value) }
+                }
+                try!(util::handle_error(attr.with_c_str(|c_attr| match value {
+                    Some(value) => value.with_c_str(|value| it(e, c_attr, value)),
+                    None => it(e, c_attr, ptr::null())
+                }), "udev_enumerate_add_match_property", Some(attr)));
+            },
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs(ref mut scan) => scan.filter = scan.filter.clone().match_property(attr, value),
         }
-        attr.with_c_str(|attr| match value {
-            Some(value) => value.with_c_str(|value| it(&self, attr, value)),
-            None => it(&self, attr, ptr::null())
-        });
-        self
+        Ok(self)
     }
 
     /// Only include the specified device and its children.
     ///
     /// All devices added by future scans (until the parent is changed/cleared)
     /// will have/be the specified parent.
-    pub fn match_parent(self, parent: &Device) -> Enumerator<'u> {
-        unsafe {
-            util::handle_error(libudev_c::udev_enumerate_add_match_parent(self.enumerator, device::device_get_dev(parent)));
+    pub fn match_parent(mut self, parent: &Device) -> Result<Enumerator, Error> {
+        match self.enumerator {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(e) => {
+                // libudev needs its own device; look up devices from other contexts again.
+                let found = match unsafe { device::device_get_dev(parent) } {
+                    Some(_) => None,
+                    None => match self.udev.device(&parent.syspath()) {
+                        Some(dev) => Some(dev),
+                        None => return Err(error::os(ENODEV, "udev_enumerate_add_match_parent",
+                                                     parent.syspath().as_str()))
+                    }
+                };
+                let parent = found.as_ref().unwrap_or(parent);
+                try!(util::handle_error(unsafe {
+                    libudev_c::udev_enumerate_add_match_parent(e, device::device_get_dev(parent).unwrap())
+                }, "udev_enumerate_add_match_parent", parent.syspath().as_str()));
+            },
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs(ref mut scan) => scan.parent = Some(parent.syspath()),
         }
-        self
+        Ok(self)
     }
 
     /// Remove the parent restriction.
     ///
     /// Clear the current parent match. Future scans will add devices regardless of their parents.
-    pub fn clear_parent(self) -> Enumerator<'u> {
-        unsafe {
-            util::handle_error(libudev_c::udev_enumerate_add_match_parent(self.enumerator, ptr::null_mut()));
+    pub fn clear_parent(mut self) -> Result<Enumerator, Error> {
+        match self.enumerator {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(e) => try!(util::handle_error(unsafe {
+                libudev_c::udev_enumerate_add_match_parent(e, ptr::null_mut())
+            }, "udev_enumerate_add_match_parent", None)),
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs(ref mut scan) => scan.parent = None,
         }
-        self
+        Ok(self)
     }
 
     /// Only include devices with the specified tag.
     ///
     /// All devices added by future scans will match the specified tag.
-    pub fn match_tag(self, tag: &str) -> Enumerator<'u> {
-        tag.with_c_str(|tag| util::handle_error( unsafe {
-            libudev_c::udev_enumerate_add_match_tag(self.enumerator, tag)
-        }));
-        self
+    pub fn match_tag(mut self, tag: &str) -> Result<Enumerator, Error> {
+        match self.enumerator {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(e) => try!(tag.with_c_str(|c_tag| util::handle_error(unsafe {
+                libudev_c::udev_enumerate_add_match_tag(e, c_tag)
+            }, "udev_enumerate_add_match_tag", Some(tag)))),
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs(ref mut scan) => scan.filter = scan.filter.clone().match_tag(tag),
+        }
+        Ok(self)
     }
 
     /// Include only initialized devices.
     ///
     /// All devices added by future scans will be initialized.
-    pub fn match_is_initialized(self) -> Enumerator<'u> {
-        util::handle_error(unsafe {
-            libudev_c::udev_enumerate_add_match_is_initialized(self.enumerator)
-        });
-        self
+    pub fn match_is_initialized(mut self) -> Result<Enumerator, Error> {
+        match self.enumerator {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(e) => try!(util::handle_error(unsafe {
+                libudev_c::udev_enumerate_add_match_is_initialized(e)
+            }, "udev_enumerate_add_match_is_initialized", None)),
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs(ref mut scan) => scan.initialized = true,
+        }
+        Ok(self)
     }
 
     /// Include devices with the specified sysname.
@@ -167,40 +261,65 @@ impl<'u> Enumerator<'u> {
     /// All devices added by future scans will match either the specified sysname or a sysname
     /// specified in a previous invocation of this function (on this enumerator). If this function
     /// has never been called, devices will not be filtered by sysname.
-    pub fn match_sysname(self, sysname: &str) -> Enumerator<'u> {
-        sysname.with_c_str(|sysname| util::handle_error( unsafe {
-            libudev_c::udev_enumerate_add_match_sysname(self.enumerator, sysname)
-        }));
-        self
+    pub fn match_sysname(mut self, sysname: &str) -> Result<Enumerator, Error> {
+        match self.enumerator {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(e) => try!(sysname.with_c_str(|c_sysname| util::handle_error(unsafe {
+                libudev_c::udev_enumerate_add_match_sysname(e, c_sysname)
+            }, "udev_enumerate_add_match_sysname", Some(sysname)))),
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs(ref mut scan) => scan.filter = scan.filter.clone().match_sysname(sysname),
+        }
+        Ok(self)
     }
 
     /// Manually include a device.
     ///
     /// Manually add a device to the enumerator bypassing matches. According to the libudev
     /// documentation, this can be useful for determine device dependency order (see iter below).
-    pub fn add_device(self, device: &Device) -> Enumerator<'u> {
-        device.syspath().with_c_str(|syspath| util::handle_error(unsafe{
-            libudev_c::udev_enumerate_add_syspath(self.enumerator, syspath)
-        }));
-        self
+    pub fn add_device(mut self, device: &Device) -> Result<Enumerator, Error> {
+        let syspath = device.syspath();
+        match self.enumerator {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(e) => try!(syspath.with_c_str(|c_syspath| util::handle_error(unsafe {
+                libudev_c::udev_enumerate_add_syspath(e, c_syspath)
+            }, "udev_enumerate_add_syspath", syspath.as_str()))),
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs(ref mut scan) => scan.add(syspath),
+        }
+        Ok(self)
     }
 
     /// Scan subsystems
     ///
     /// Scan sysfs for subsystems matching all previously applied constraints and add them to the
     /// enumerator.
-    pub fn scan_subsystems(self) -> Enumerator<'u> {
-        util::handle_error(unsafe { libudev_c::udev_enumerate_scan_subsystems(self.enumerator) });
-        self
+    pub fn scan_subsystems(mut self) -> Result<Enumerator, Error> {
+        match self.enumerator {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(e) => try!(util::handle_error(unsafe {
+                libudev_c::udev_enumerate_scan_subsystems(e)
+            }, "udev_enumerate_scan_subsystems", None)),
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs(ref mut scan) => try!(scan.scan_subsystems()),
+        }
+        Ok(self)
     }
 
     /// Scan devices
     ///
     /// Scan sysfs for devices matching all previously applied constraints and add them to the
     /// enumerator.
-    pub fn scan_devices(self) -> Enumerator<'u> {
-        util::handle_error(unsafe { libudev_c::udev_enumerate_scan_devices(self.enumerator) });
-        self
+    pub fn scan_devices(mut self) -> Result<Enumerator, Error> {
+        match self.enumerator {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(e) => try!(util::handle_error(unsafe {
+                libudev_c::udev_enumerate_scan_devices(e)
+            }, "udev_enumerate_scan_devices", None)),
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs(ref mut scan) => try!(scan.scan_devices()),
+        }
+        Ok(self)
     }
 
     /// Iterate over all devices that have been added to this iterator in dependency order.
@@ -211,25 +330,54 @@ impl<'u> Enumerator<'u> {
     /// to the enumerator (`add_device`), or haven't called a scan method (`scan_subsystems` or
     /// `scan_devices`), this iterator will be empty.
     pub fn iter(&self) -> DeviceIterator {
-        unsafe {
-            iterator::iterator(self, libudev_c::udev_enumerate_get_list_entry(self.enumerator))
-        }.filter_map(|(this, key, _)| this.udev.device(&Path::new(key)))
+        self.list().filter_map(|(this, key, _)| this.udev.device(&Path::new(key)))
     }
 
     /// Same as `iter` but avoid creating device objects.
     pub fn iter_paths(&self) -> DevicePathIterator {
-        unsafe {
-            iterator::iterator(self, libudev_c::udev_enumerate_get_list_entry(self.enumerator))
-        }.map(|(_, key, _)| Path::new(key))
+        self.list().map(|(_, key, _)| Path::new(key))
+    }
+
+    /// Trigger a synthetic event for every scanned device (like `udevadm trigger`).
+    ///
+    /// Requires write access to sysfs (usually root).
+    pub fn trigger_all(&self, action: Action) -> Result<(), Error> {
+        self.trigger_all_with(&Trigger::new(action))
     }
 
+    /// Trigger a synthetic event for every scanned device with a UUID, arguments or waiting (see
+    /// `Trigger`).
+    ///
+    /// When waiting, this returns once udev has processed the events of every device.
+    ///
+    /// Devices that disappeared since the scan are skipped. If triggering fails for other
+    /// devices, the remaining devices are still triggered (and waited for) and the failures are
+    /// returned together as an `Error::Multiple`.
+    pub fn trigger_all_with(&self, t: &Trigger) -> Result<(), Error> {
+        trigger::trigger(&self.udev, self.iter_paths().collect(), t)
+    }
+
+    fn list<'s>(&'s self) -> UdevIterator<'s, Enumerator> {
+        match self.enumerator {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(e) => unsafe {
+                iterator::iterator(self, libudev_c::udev_enumerate_get_list_entry(e))
+            },
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs(ref scan) => {
+                iterator::owned_iterator(self, scan.syspaths.iter().map(|path| (path.as_vec(), None)).collect())
+            }
+        }
+    }
 }
 
-#[unsafe_destructor]
-impl<'p> Drop for Enumerator<'p> {
+impl Drop for Enumerator {
     fn drop(&mut self) {
-        unsafe {
-            libudev_c::udev_enumerate_unref(self.enumerator);
+        match self.enumerator {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(e) => unsafe { libudev_c::udev_enumerate_unref(e); },
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs(_) => ()
         }
     }
 }
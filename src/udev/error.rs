@@ -0,0 +1,185 @@
+// This file is part of udev-rs.
+// 
+// Copyright 2014 Steven Allen <steven@stebalien.com>
+// 
+// udev-rs is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation; either version 2.1 of the License, or
+// (at your option) any later version.
+// 
+// udev-rs is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+// 
+// You should have received a copy of the GNU Lesser General Public License
+// along with udev-rs; If not, see <http://www.gnu.org/licenses/>.
+
+use std::{error, fmt, os};
+use std::io::{IoError, OtherIoError};
+
+use libc::{c_int, ENOBUFS, ETIMEDOUT};
+
+/// An error reported by a udev operation.
+///
+/// Every error records the operation that failed (usually the name of the underlying libudev
+/// function) and, where there is one, the object involved (a subsystem, tag, path, etc.).
+#[deriving(Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The operation failed with the given errno.
+    Os {
+        errno: c_int,
+        op: &'static str,
+        object: Option<String>,
+    },
+    /// The operation failed but libudev didn't say why.
+    ///
+    /// When creating a hardware database, this usually means that the database is corrupt.
+    Unknown {
+        op: &'static str,
+        object: Option<String>,
+    },
+    /// A sysfs attribute couldn't be parsed.
+    Parse {
+        /// The attribute's name.
+        attribute: String,
+        /// The device's syspath.
+        device: String,
+        /// The attribute's value.
+        value: String,
+    },
+    /// A monitor's receive buffer overflowed and events were lost.
+    ///
+    /// The monitor is still usable but devices may have been added, changed or removed without
+    /// notice. Rescan with an `Enumerator` to catch up.
+    Overflow,
+    /// The operation didn't complete in time.
+    Timeout {
+        op: &'static str,
+    },
+    /// An operation on several objects failed for some of them (e.g. triggering devices).
+    ///
+    /// The operation was still carried out for the other objects.
+    Multiple {
+        op: &'static str,
+        /// The individual errors (never empty).
+        errors: Vec<Error>,
+    },
+}
+
+// Crate Private
+pub fn os(errno: c_int, op: &'static str, object: Option<&str>) -> Error {
+    Error::Os {
+        errno: errno,
+        op: op,
+        object: object.map(|o| o.to_string()),
+    }
+}
+
+pub fn unknown(op: &'static str, object: Option<&str>) -> Error {
+    Error::Unknown {
+        op: op,
+        object: object.map(|o| o.to_string()),
+    }
+}
+
+pub fn parse(attribute: &str, device: &Path, value: &str) -> Error {
+    Error::Parse {
+        attribute: attribute.to_string(),
+        device: device.display().to_string(),
+        value: value.to_string(),
+    }
+}
+
+impl Error {
+    /// Get the errno associated with this error (if any).
+    pub fn errno(&self) -> Option<c_int> {
+        match *self {
+            Error::Os { errno, .. } => Some(errno),
+            Error::Overflow => Some(ENOBUFS),
+            Error::Timeout { .. } => Some(ETIMEDOUT),
+            Error::Multiple { ref errors, .. } => errors[0].errno(),
+            Error::Unknown { .. } | Error::Parse { .. } => None,
+        }
+    }
+
+    /// Get the name of the operation that failed.
+    pub fn op(&self) -> &'static str {
+        match *self {
+            Error::Os { op, .. } | Error::Unknown { op, .. } | Error::Timeout { op } => op,
+            Error::Multiple { op, .. } => op,
+            Error::Parse { .. } => "parse attribute",
+            Error::Overflow => "udev_monitor_receive_device",
+        }
+    }
+
+    /// Get the object involved in the failed operation (if any).
+    pub fn object<'s>(&'s self) -> Option<&'s str> {
+        match *self {
+            Error::Os { ref object, .. } | Error::Unknown { ref object, .. } => {
+                object.as_ref().map(|o| o.as_slice())
+            }
+            Error::Parse { ref attribute, .. } => Some(attribute.as_slice()),
+            Error::Overflow | Error::Timeout { .. } | Error::Multiple { .. } => None,
+        }
+    }
+
+    /// Convert this error into an `IoError`.
+    ///
+    /// Errors without an errno are converted into `OtherIoError`s.
+    pub fn to_io_error(&self) -> IoError {
+        match self.errno() {
+            Some(errno) => IoError::from_errno(errno as uint, false),
+            None => IoError {
+                kind: OtherIoError,
+                desc: "unknown udev error",
+                detail: Some(self.to_string()),
+            },
+        }
+    }
+}
+
+impl fmt::Show for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Parse { ref attribute, ref device, ref value } => {
+                return write!(f, "invalid value for attribute {} of {}: {}", attribute, device, value);
+            }
+            Error::Overflow => return write!(f, "monitor receive buffer overflowed, events were lost"),
+            Error::Timeout { op } => return write!(f, "{} timed out", op),
+            Error::Multiple { op, ref errors } => {
+                try!(write!(f, "{} failed for {} objects:", op, errors.len()));
+                for error in errors.iter() {
+                    try!(write!(f, " {};", error));
+                }
+                return Ok(());
+            }
+            _ => ()
+        }
+        try!(write!(f, "{}", self.op()));
+        if let Some(object) = self.object() {
+            try!(write!(f, "({})", object));
+        }
+        match *self {
+            Error::Os { errno, .. } => write!(f, " failed: {}", os::error_string(errno as uint)),
+            _ => write!(f, " failed"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Os { .. } => "udev operation failed",
+            Error::Unknown { .. } => "udev operation failed for an unknown reason",
+            Error::Parse { .. } => "invalid attribute value",
+            Error::Overflow => "monitor receive buffer overflowed",
+            Error::Timeout { .. } => "udev operation timed out",
+            Error::Multiple { .. } => "udev operation failed for some objects",
+        }
+    }
+
+    fn detail(&self) -> Option<String> {
+        Some(self.to_string())
+    }
+}
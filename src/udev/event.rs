@@ -0,0 +1,89 @@
+// This file is part of udev-rs.
+// 
+// Copyright 2014 Steven Allen <steven@stebalien.com>
+// 
+// udev-rs is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation; either version 2.1 of the License, or
+// (at your option) any later version.
+// 
+// udev-rs is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+// 
+// You should have received a copy of the GNU Lesser General Public License
+// along with udev-rs; If not, see <http://www.gnu.org/licenses/>.
+
+use std::str::FromStr;
+use std::time::Duration;
+
+/// The action of a uevent.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub enum Action {
+    Add,
+    Remove,
+    Change,
+    Move,
+    Online,
+    Offline,
+    /// A driver was bound to the device.
+    Bind,
+    /// A driver was unbound from the device.
+    Unbind,
+    Other(String)
+}
+
+/// The metadata of a uevent.
+#[deriving(Clone, Show)]
+pub struct Event {
+    pub action: Action,
+    pub seqnum: u64,
+    /// The device's previous path (minus `/sys`) for `Move` events.
+    pub devpath_old: Option<Path>,
+    /// The driver bound to the device when the event was sent.
+    pub driver: Option<String>,
+    /// The UUID passed when the event was synthesized by writing to the device's `uevent` file.
+    pub synth_uuid: Option<String>,
+    /// The `KEY=VALUE` arguments passed along with the UUID (without the `SYNTH_ARG_` prefix).
+    pub synth_args: Vec<(String, String)>,
+    /// When the event was received (wall-clock time since the Unix epoch).
+    pub received: Duration,
+}
+
+impl Action {
+    /// Get the action's name as used by the kernel (e.g. `add`).
+    pub fn as_str(&self) -> &str {
+        use self::Action::*;
+
+        match *self {
+            Add         => "add",
+            Remove      => "remove",
+            Change      => "change",
+            Move        => "move",
+            Online      => "online",
+            Offline     => "offline",
+            Bind        => "bind",
+            Unbind      => "unbind",
+            Other(ref s) => s.as_slice(),
+        }
+    }
+}
+
+impl FromStr for Action {
+    fn from_str(s: &str) -> Option<Action> {
+        use self::Action::*;
+
+        match s {
+            "add"       => Some(Add),
+            "remove"    => Some(Remove),
+            "change"    => Some(Change),
+            "move"      => Some(Move),
+            "online"    => Some(Online),
+            "offline"   => Some(Offline),
+            "bind"      => Some(Bind),
+            "unbind"    => Some(Unbind),
+            _           => Some(Other(s.to_string())),
+        }
+    }
+}
@@ -0,0 +1,475 @@
+// This file is part of udev-rs.
+// 
+// Copyright 2014 Steven Allen <steven@stebalien.com>
+// 
+// udev-rs is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation; either version 2.1 of the License, or
+// (at your option) any later version.
+// 
+// udev-rs is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+// 
+// You should have received a copy of the GNU Lesser General Public License
+// along with udev-rs; If not, see <http://www.gnu.org/licenses/>.
+
+use udev::device::Device;
+use udev::util;
+use udev::snapshot::DeviceSnapshot;
+
+/// Something a `Filter` can be matched against.
+///
+/// Implemented by `Device` and `DeviceSnapshot`.
+pub trait Matchable {
+    /// Get the full path to the device (including `/sys`) as raw bytes.
+    fn syspath_bytes(&self) -> &[u8];
+    /// Get the device name as raw bytes.
+    fn sysname_bytes(&self) -> &[u8];
+    /// Get the device's subsystem as raw bytes.
+    fn subsystem_bytes(&self) -> Option<&[u8]>;
+    /// Look up a property as raw bytes.
+    fn property_value_bytes(&self, name: &str) -> Option<&[u8]>;
+    /// Look up a sysfs attribute as raw bytes (`None` if it can't be read).
+    fn attribute_value(&self, name: &str) -> Option<Vec<u8>>;
+    /// Check whether the device has a given tag.
+    fn has_tag(&self, tag: &str) -> bool;
+    /// Check whether the device has a given devlink.
+    fn has_devlink(&self, link: &Path) -> bool;
+}
+
+/// A userspace device filter.
+///
+/// libudev can only filter monitor events by subsystem/devtype and tag (see
+/// `Monitor::filter_by_subsystem` etc.). A `Filter` supports the same matches as an
+/// `Enumerator` and can be attached to a monitor with `Monitor::filter_by` or checked directly
+/// with `matches`.
+///
+/// Sysnames and property/attribute values are shell-style globs (`*`, `?`, `[a-z]`, `[!0-9]`).
+/// As with an `Enumerator`:
+///
+/// * a device must match at least one of the specified subsystems, sysnames and properties
+///   (of each kind),
+/// * a device must match all of the specified attributes, tags and devlinks,
+/// * a device is excluded if it matches any of the negated matches.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub struct Filter {
+    subsystems: Vec<String>,
+    not_subsystems: Vec<String>,
+    sysnames: Vec<String>,
+    not_sysnames: Vec<String>,
+    properties: Vec<(String, Option<String>)>,
+    not_properties: Vec<(String, Option<String>)>,
+    attributes: Vec<(String, Option<String>)>,
+    not_attributes: Vec<(String, Option<String>)>,
+    tags: Vec<String>,
+    devlinks: Vec<Path>,
+    parent: Option<Path>,
+}
+
+// Crate Private
+pub fn filter_subsystems(filter: &Filter) -> &[String] {
+    filter.subsystems.as_slice()
+}
+
+impl Filter {
+    /// Create a filter that matches every device.
+    pub fn new() -> Filter {
+        Filter {
+            subsystems: Vec::new(),
+            not_subsystems: Vec::new(),
+            sysnames: Vec::new(),
+            not_sysnames: Vec::new(),
+            properties: Vec::new(),
+            not_properties: Vec::new(),
+            attributes: Vec::new(),
+            not_attributes: Vec::new(),
+            tags: Vec::new(),
+            devlinks: Vec::new(),
+            parent: None,
+        }
+    }
+
+    /// Include devices in the specified subsystem.
+    pub fn match_subsystem(mut self, subsystem: &str) -> Filter {
+        self.subsystems.push(subsystem.to_string());
+        self
+    }
+
+    /// Exclude devices in the specified subsystem.
+    pub fn match_not_subsystem(mut self, subsystem: &str) -> Filter {
+        self.not_subsystems.push(subsystem.to_string());
+        self
+    }
+
+    /// Include devices with a sysname matching the specified glob.
+    pub fn match_sysname(mut self, sysname: &str) -> Filter {
+        self.sysnames.push(sysname.to_string());
+        self
+    }
+
+    /// Exclude devices with a sysname matching the specified glob.
+    pub fn match_not_sysname(mut self, sysname: &str) -> Filter {
+        self.not_sysnames.push(sysname.to_string());
+        self
+    }
+
+    /// Include devices with the specified property.
+    ///
+    /// If a value is specified, the property's value must match it.
+    pub fn match_property(mut self, name: &str, value: Option<&str>) -> Filter {
+        self.properties.push((name.to_string(), value.map(|v| v.to_string())));
+        self
+    }
+
+    /// Exclude devices with the specified property.
+    ///
+    /// If a value is specified, only devices where the property's value matches it are excluded.
+    pub fn match_not_property(mut self, name: &str, value: Option<&str>) -> Filter {
+        self.not_properties.push((name.to_string(), value.map(|v| v.to_string())));
+        self
+    }
+
+    /// Only include devices with the specified sysfs attribute.
+    ///
+    /// If a value is specified, the attribute's value (minus trailing whitespace) must match it.
+    /// Attributes are read from sysfs when the filter is checked so they can't be matched on
+    /// devices that have already been removed.
+    pub fn match_attribute(mut self, attr: &str, value: Option<&str>) -> Filter {
+        self.attributes.push((attr.to_string(), value.map(|v| v.to_string())));
+        self
+    }
+
+    /// Exclude devices with the specified sysfs attribute.
+    ///
+    /// If a value is specified, only devices where the attribute's value matches it are
+    /// excluded.
+    pub fn match_not_attribute(mut self, attr: &str, value: Option<&str>) -> Filter {
+        self.not_attributes.push((attr.to_string(), value.map(|v| v.to_string())));
+        self
+    }
+
+    /// Only include devices with the specified tag.
+    pub fn match_tag(mut self, tag: &str) -> Filter {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    /// Only include devices with the specified devlink (e.g. `/dev/disk/by-uuid/...`).
+    pub fn match_devlink(mut self, link: &Path) -> Filter {
+        self.devlinks.push(link.clone());
+        self
+    }
+
+    /// Only include the device with the specified syspath and its children.
+    ///
+    /// Replaces any previously specified parent.
+    pub fn match_parent(mut self, syspath: &Path) -> Filter {
+        self.parent = Some(syspath.clone());
+        self
+    }
+
+    /// Check whether a device matches this filter.
+    pub fn matches<T: Matchable>(&self, dev: &T) -> bool {
+        let subsystem = dev.subsystem_bytes().unwrap_or(b"");
+        if !self.subsystems.is_empty() && !self.subsystems.iter().any(|s| s.as_bytes() == subsystem) {
+            return false;
+        }
+        if self.not_subsystems.iter().any(|s| s.as_bytes() == subsystem) {
+            return false;
+        }
+
+        let sysname = dev.sysname_bytes();
+        if !self.sysnames.is_empty() && !self.sysnames.iter().any(|p| glob(p.as_bytes(), sysname)) {
+            return false;
+        }
+        if self.not_sysnames.iter().any(|p| glob(p.as_bytes(), sysname)) {
+            return false;
+        }
+
+        if let Some(ref parent) = self.parent {
+            let (syspath, parent) = (dev.syspath_bytes(), parent.as_vec());
+            let in_subtree = syspath.starts_with(parent) &&
+                (syspath.len() == parent.len() || syspath[parent.len()] == b'/');
+            if !in_subtree {
+                return false;
+            }
+        }
+
+        if !self.tags.iter().all(|tag| dev.has_tag(tag.as_slice())) {
+            return false;
+        }
+        if !self.devlinks.iter().all(|link| dev.has_devlink(link)) {
+            return false;
+        }
+
+        let property_matches = |&(ref name, ref value): &(String, Option<String>)| {
+            match dev.property_value_bytes(name.as_slice()) {
+                Some(actual) => value.as_ref().map_or(true, |v| glob(v.as_bytes(), actual)),
+                None => false
+            }
+        };
+        if !self.properties.is_empty() && !self.properties.iter().any(|m| property_matches(m)) {
+            return false;
+        }
+        if self.not_properties.iter().any(|m| property_matches(m)) {
+            return false;
+        }
+
+        let attribute_matches = |&(ref name, ref value): &(String, Option<String>)| {
+            match dev.attribute_value(name.as_slice()) {
+                Some(actual) => value.as_ref().map_or(true, |v| glob(v.as_bytes(), util::trim_end(actual.as_slice()))),
+                None => false
+            }
+        };
+        if !self.attributes.iter().all(|m| attribute_matches(m)) {
+            return false;
+        }
+        if self.not_attributes.iter().any(|m| attribute_matches(m)) {
+            return false;
+        }
+
+        true
+    }
+}
+
+impl Matchable for Device {
+    fn syspath_bytes(&self) -> &[u8] {
+        self.syspath_bytes()
+    }
+
+    fn sysname_bytes(&self) -> &[u8] {
+        self.sysname_bytes()
+    }
+
+    fn subsystem_bytes(&self) -> Option<&[u8]> {
+        self.subsystem_bytes()
+    }
+
+    fn property_value_bytes(&self, name: &str) -> Option<&[u8]> {
+        self.property_value_bytes(name)
+    }
+
+    fn attribute_value(&self, name: &str) -> Option<Vec<u8>> {
+        self.attribute_bytes(name).ok().map(|value| value.to_vec())
+    }
+
+    fn has_tag(&self, tag: &str) -> bool {
+        self.has_tag(tag)
+    }
+
+    fn has_devlink(&self, link: &Path) -> bool {
+        self.iter_devlinks().any(|l| l == *link)
+    }
+}
+
+impl Matchable for DeviceSnapshot {
+    fn syspath_bytes(&self) -> &[u8] {
+        self.syspath().as_vec()
+    }
+
+    fn sysname_bytes(&self) -> &[u8] {
+        self.sysname_bytes()
+    }
+
+    fn subsystem_bytes(&self) -> Option<&[u8]> {
+        self.subsystem_bytes()
+    }
+
+    fn property_value_bytes(&self, name: &str) -> Option<&[u8]> {
+        self.property_value_bytes(name)
+    }
+
+    fn attribute_value(&self, name: &str) -> Option<Vec<u8>> {
+        self.attribute_bytes(name).map(|value| value.to_vec())
+    }
+
+    fn has_tag(&self, tag: &str) -> bool {
+        self.has_tag(tag)
+    }
+
+    fn has_devlink(&self, link: &Path) -> bool {
+        self.devlinks().iter().any(|l| l == link)
+    }
+}
+
+/// Match `text` against a shell-style glob (like `fnmatch(3)` without flags).
+fn glob(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0u, 0u);
+    // The pattern position after the last `*` and the text position it has consumed up to.
+    let mut backtrack = None;
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == b'*' {
+            p += 1;
+            backtrack = Some((p, t));
+            continue;
+        }
+        match if p < pattern.len() { glob_step(pattern, p, text[t]) } else { None } {
+            Some(next) => {
+                p = next;
+                t += 1;
+            },
+            None => match backtrack {
+                Some((bp, bt)) => {
+                    p = bp;
+                    t = bt + 1;
+                    backtrack = Some((bp, bt + 1));
+                },
+                None => return false
+            }
+        }
+    }
+    pattern.slice_from(p).iter().all(|&c| c == b'*')
+}
+
+// Match a single (non-`*`) pattern element at `p` against `c`, returning the next pattern position.
+fn glob_step(pattern: &[u8], p: uint, c: u8) -> Option<uint> {
+    match pattern[p] {
+        b'?' => Some(p + 1),
+        b'\\' if p + 1 < pattern.len() => if pattern[p + 1] == c { Some(p + 2) } else { None },
+        b'[' => match glob_class(pattern, p + 1, c) {
+            Some((true, next)) => Some(next),
+            Some((false, _)) => None,
+            // An unterminated class is a literal `[`.
+            None => if c == b'[' { Some(p + 1) } else { None },
+        },
+        x => if x == c { Some(p + 1) } else { None },
+    }
+}
+
+// Check whether `c` is in the character class starting at `p` (just after the `[`). Returns the
+// result and the position after the closing `]`, or `None` if the class is unterminated.
+fn glob_class(pattern: &[u8], mut p: uint, c: u8) -> Option<(bool, uint)> {
+    let negate = p < pattern.len() && (pattern[p] == b'!' || pattern[p] == b'^');
+    if negate {
+        p += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    while p < pattern.len() {
+        let lo = pattern[p];
+        if lo == b']' && !first {
+            return Some((matched != negate, p + 1));
+        }
+        first = false;
+        if p + 2 < pattern.len() && pattern[p + 1] == b'-' && pattern[p + 2] != b']' {
+            matched = matched || (lo <= c && c <= pattern[p + 2]);
+            p += 3;
+        } else {
+            matched = matched || lo == c;
+            p += 1;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use udev::util;
+    use udev::snapshot::DeviceSnapshot;
+    use super::{Filter, Matchable, glob};
+
+    fn dev(devpath: &str, subsystem: &str, properties: &[(&str, &str)]) -> DeviceSnapshot {
+        util::snapshot(devpath, subsystem, properties)
+    }
+
+    struct WithAttributes {
+        dev: DeviceSnapshot,
+        attributes: Vec<(&'static str, &'static str)>,
+    }
+
+    impl Matchable for WithAttributes {
+        fn syspath_bytes(&self) -> &[u8] { self.dev.syspath().as_vec() }
+        fn sysname_bytes(&self) -> &[u8] { self.dev.sysname_bytes() }
+        fn subsystem_bytes(&self) -> Option<&[u8]> { self.dev.subsystem_bytes() }
+        fn property_value_bytes(&self, name: &str) -> Option<&[u8]> { self.dev.property_value_bytes(name) }
+        fn has_tag(&self, tag: &str) -> bool { self.dev.has_tag(tag) }
+        fn has_devlink(&self, link: &Path) -> bool { self.dev.devlinks().iter().any(|l| l == link) }
+        fn attribute_value(&self, name: &str) -> Option<Vec<u8>> {
+            self.attributes.iter().find(|&&(n, _)| n == name).map(|&(_, v)| v.as_bytes().to_vec())
+        }
+    }
+
+    #[test]
+    fn test_glob() {
+        assert!(glob(b"sd*", b"sda1"));
+        assert!(glob(b"sd?", b"sda"));
+        assert!(!glob(b"sd?", b"sda1"));
+        assert!(glob(b"sd[a-c][0-9]", b"sdb3"));
+        assert!(!glob(b"sd[!a-c]*", b"sdb3"));
+        assert!(glob(b"*-part*", b"usb-disk-part2"));
+        assert!(glob(b"a\\*", b"a*"));
+        assert!(!glob(b"a\\*", b"ab"));
+        assert!(glob(b"", b""));
+        assert!(!glob(b"", b"a"));
+    }
+
+    #[test]
+    fn test_sysname_subsystem() {
+        let sda1 = dev("/devices/pci0000:00/ata1/host0/block/sda/sda1", "block", &[]);
+        let tty = dev("/devices/virtual/tty/tty1", "tty", &[]);
+
+        let filter = Filter::new().match_sysname("sd*");
+        assert!(filter.matches(&sda1));
+        assert!(!filter.matches(&tty));
+
+        let filter = Filter::new().match_subsystem("tty").match_not_sysname("tty[0-9]");
+        assert!(!filter.matches(&sda1));
+        assert!(!filter.matches(&tty));
+
+        assert!(Filter::new().match_not_subsystem("tty").matches(&sda1));
+    }
+
+    #[test]
+    fn test_properties() {
+        let disk = dev("/devices/virtual/block/loop0", "block", &[("ID_FS_TYPE", "ext4"), ("ID_BUS", "usb")]);
+
+        assert!(Filter::new().match_property("ID_FS_TYPE", Some("ext*")).matches(&disk));
+        assert!(Filter::new().match_property("ID_FS_TYPE", None).matches(&disk));
+        assert!(!Filter::new().match_property("ID_FS_TYPE", Some("vfat")).matches(&disk));
+        // Properties are OR'ed.
+        assert!(Filter::new()
+                .match_property("ID_FS_TYPE", Some("vfat"))
+                .match_property("ID_BUS", Some("usb"))
+                .matches(&disk));
+        assert!(!Filter::new().match_not_property("ID_BUS", None).matches(&disk));
+    }
+
+    #[test]
+    fn test_devlinks_tags() {
+        let disk = dev("/devices/virtual/block/loop0", "block", &[
+            ("DEVLINKS", "/dev/disk/by-uuid/1234 /dev/disk/by-label/root"),
+            ("TAGS", ":systemd:"),
+        ]);
+        assert!(Filter::new().match_devlink(&Path::new("/dev/disk/by-uuid/1234")).matches(&disk));
+        assert!(!Filter::new().match_devlink(&Path::new("/dev/disk/by-uuid/5678")).matches(&disk));
+        assert!(Filter::new().match_tag("systemd").matches(&disk));
+        assert!(!Filter::new().match_tag("systemd").match_tag("uaccess").matches(&disk));
+    }
+
+    #[test]
+    fn test_parent() {
+        let parent = Path::new("/sys/devices/pci0000:00/usb1");
+        let filter = Filter::new().match_parent(&parent);
+        assert!(filter.matches(&dev("/devices/pci0000:00/usb1", "usb", &[])));
+        assert!(filter.matches(&dev("/devices/pci0000:00/usb1/1-1", "usb", &[])));
+        assert!(!filter.matches(&dev("/devices/pci0000:00/usb10", "usb", &[])));
+    }
+
+    #[test]
+    fn test_attributes() {
+        let d = WithAttributes {
+            dev: dev("/devices/virtual/block/sda", "block", &[]),
+            attributes: vec![("removable", "1\n"), ("size", "0")],
+        };
+        assert!(Filter::new().match_attribute("removable", Some("1")).matches(&d));
+        // Attributes are AND'ed.
+        assert!(!Filter::new()
+                .match_attribute("removable", Some("1"))
+                .match_attribute("size", Some("[1-9]*"))
+                .matches(&d));
+        assert!(!Filter::new().match_attribute("ro", None).matches(&d));
+        assert!(!Filter::new().match_not_attribute("size", Some("0")).matches(&d));
+    }
+}
@@ -15,35 +15,42 @@
 // You should have received a copy of the GNU Lesser General Public License
 // along with udev-rs; If not, see <http://www.gnu.org/licenses/>.
 
+use std::str;
+
 use udev::{
     libudev_c,
     iterator
 };
-use udev::iterator::MappedIterator;
+use udev::iterator::{
+    MappedIterator,
+    FilterMappedIterator,
+};
 use udev::udev::Udev;
 
 #[doc(hidden)]
-pub type HwdbIterator<'p> = MappedIterator<'p, Hwdb<'p>, (&'p str, &'p str)>;
+pub type HwdbIterator<'p> = FilterMappedIterator<'p, Hwdb, (&'p str, &'p str)>;
+#[doc(hidden)]
+pub type HwdbBytesIterator<'p> = MappedIterator<'p, Hwdb, (&'p [u8], &'p [u8])>;
 
-pub struct Hwdb<'u> {
-    udev: &'u Udev,
+pub struct Hwdb {
+    udev: Udev,
     hwdb: libudev_c::udev_hwdb
 }
 
-pub struct Query<'h, 'u: 'h> {
-    hwdb: &'h mut Hwdb<'u>,
+pub struct Query<'h> {
+    hwdb: &'h mut Hwdb,
     entry: libudev_c::udev_list_entry
 }
 
 // Crate Private
 pub unsafe fn hwdb(udev: &Udev, hwdb: libudev_c::udev_hwdb) -> Hwdb {
-    Hwdb { udev: udev, hwdb: hwdb }
+    Hwdb { udev: udev.clone(), hwdb: hwdb }
 }
 
-impl<'u> Hwdb<'u> {
+impl Hwdb {
     /// Get the udev context.
     pub fn udev(&self) -> &Udev {
-        self.udev
+        &self.udev
     }
 
     /// Query the hardware database.
@@ -51,7 +58,7 @@ impl<'u> Hwdb<'u> {
     /// # Note
     ///
     /// Only one query can exist at a time.
-    pub fn query<'s>(&'s mut self, modalias: &str) -> Query<'s, 'u> {
+    pub fn query<'s>(&'s mut self, modalias: &str) -> Query<'s> {
         // HACK: take reference here because we can't reference self.hwdb inside the closure.
         let entry = modalias.with_c_str(|modalias| {
             unsafe { libudev_c::udev_hwdb_get_properties_list_entry(self.hwdb, modalias) }
@@ -64,17 +71,28 @@ impl<'u> Hwdb<'u> {
     }
 }
 
-impl<'h, 'u> Query<'h, 'u> {
+impl<'h> Query<'h> {
     /// Iterate over the properties returned by this query.
+    ///
+    /// Properties with a name or value that isn't valid UTF-8 are skipped (see `iter_bytes`).
     pub fn iter(&self) -> HwdbIterator {
         unsafe {
             iterator::iterator(self.hwdb, self.entry)
-        }.map(|(_, key, value)| (key, value.unwrap()))
+        }.filter_map(|(_, key, value)| match (str::from_utf8(key), value.and_then(str::from_utf8)) {
+            (Some(key), Some(value)) => Some((key, value)),
+            _ => None
+        })
+    }
+
+    /// Iterate over the properties returned by this query as raw bytes.
+    pub fn iter_bytes(&self) -> HwdbBytesIterator {
+        unsafe {
+            iterator::iterator(self.hwdb, self.entry)
+        }.map(|(_, key, value)| (key, value.unwrap_or(b"")))
     }
 }
 
-#[unsafe_destructor]
-impl<'u> Drop for Hwdb<'u> {
+impl Drop for Hwdb {
     fn drop(&mut self) {
         unsafe { libudev_c::udev_hwdb_unref(self.hwdb) };
     }
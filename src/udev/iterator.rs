@@ -14,9 +14,11 @@
 // 
 // You should have received a copy of the GNU Lesser General Public License
 // along with udev-rs; If not, see <http://www.gnu.org/licenses/>.
-
 use std::iter;
+#[cfg(feature = "sysfs")]
+use std::vec;
 
+#[cfg(feature = "libudev")]
 use udev::{
     libudev_c,
     util
@@ -30,32 +32,56 @@ use udev::{
 // Create private.
 pub struct UdevIterator<'p, Sized? T: 'p> {
     parent: &'p T,
-    entry: libudev_c::udev_list_entry
+    entries: Entries<'p>,
+}
+
+// Entries come from a libudev list or, for the sysfs backend, were collected up front.
+enum Entries<'p> {
+    #[cfg(feature = "libudev")]
+    List(libudev_c::udev_list_entry),
+    #[cfg(feature = "sysfs")]
+    Owned(vec::MoveItems<(&'p [u8], Option<&'p [u8]>)>),
 }
 
-impl<'p, Sized? T> Iterator<(&'p T, &'p str, Option<&'p str>)> for UdevIterator<'p, T> {
-    fn next(&mut self) -> Option<(&'p T, &'p str, Option<&'p str>)> {
-        if self.entry.is_null() {
-            None
-        } else {
-            let ret = Some((
-                self.parent,
-                unsafe { util::c_to_str(libudev_c::udev_list_entry_get_name(self.entry)).unwrap() },
-                unsafe { util::c_to_str(libudev_c::udev_list_entry_get_value(self.entry)) }
-            ));
-            self.entry = unsafe { libudev_c::udev_list_entry_get_next(self.entry) };
-            ret
+// Entries are yielded as raw bytes; there's no guarantee that names or values are valid UTF-8.
+impl<'p, Sized? T> Iterator<(&'p T, &'p [u8], Option<&'p [u8]>)> for UdevIterator<'p, T> {
+    fn next(&mut self) -> Option<(&'p T, &'p [u8], Option<&'p [u8]>)> {
+        let parent = self.parent;
+        match self.entries {
+            #[cfg(feature = "libudev")]
+            Entries::List(ref mut entry) => if entry.is_null() {
+                None
+            } else {
+                let ret = Some((
+                    parent,
+                    // List entries always have a name.
+                    unsafe { util::c_to_bytes(libudev_c::udev_list_entry_get_name(*entry)).unwrap() },
+                    unsafe { util::c_to_bytes(libudev_c::udev_list_entry_get_value(*entry)) }
+                ));
+                *entry = unsafe { libudev_c::udev_list_entry_get_next(*entry) };
+                ret
+            },
+            #[cfg(feature = "sysfs")]
+            Entries::Owned(ref mut entries) => entries.next().map(|(name, value)| (parent, name, value)),
         }
     }
 }
 
+#[cfg(feature = "libudev")]
 pub unsafe fn iterator<'a, Sized? T: 'a>(parent: &'a T, entry: libudev_c::udev_list_entry) -> UdevIterator<'a, T> {
     UdevIterator {
         parent: parent,
-        entry: entry,
+        entries: Entries::List(entry),
     }
 }
 
-pub type MappedIterator<'p, P: 'p, O> = iter::Map<'p, (&'p P, &'p str, Option<&'p str>), O, UdevIterator<'p, P>>;
-pub type FilterMappedIterator<'p, P: 'p, O> = iter::FilterMap<'p, (&'p P, &'p str, Option<&'p str>), O, UdevIterator<'p, P>>;
+#[cfg(feature = "sysfs")]
+pub fn owned_iterator<'a, Sized? T: 'a>(parent: &'a T, entries: Vec<(&'a [u8], Option<&'a [u8]>)>) -> UdevIterator<'a, T> {
+    UdevIterator {
+        parent: parent,
+        entries: Entries::Owned(entries.into_iter()),
+    }
+}
 
+pub type MappedIterator<'p, P: 'p, O> = iter::Map<'p, (&'p P, &'p [u8], Option<&'p [u8]>), O, UdevIterator<'p, P>>;
+pub type FilterMappedIterator<'p, P: 'p, O> = iter::FilterMap<'p, (&'p P, &'p [u8], Option<&'p [u8]>), O, UdevIterator<'p, P>>;
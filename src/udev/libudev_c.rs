@@ -25,18 +25,22 @@ pub type udev_list_entry = *mut c_void;
 pub type udev_enumerate = *mut c_void;
 pub type udev_monitor = *mut c_void;
 pub type udev_hwdb = *mut c_void;
+pub type udev_queue = *mut c_void;
 type c_str = *const c_char;
 
 #[link(name = "udev")]
 extern {
     // Udev
     pub fn udev_new() -> udev;
+    pub fn udev_ref(udev: udev) -> udev;
     pub fn udev_unref(udev: udev) -> udev;
 
     // Device
     pub fn udev_device_new_from_syspath(udev: udev, attr: c_str) -> udev_device;
     pub fn udev_device_new_from_devnum(dev: udev_device, c: c_char, d: dev_t) -> udev_device;
     pub fn udev_device_new_from_subsystem_sysname(dev: udev_device, ss: c_str, sn: c_str) -> udev_device;
+    pub fn udev_device_new_from_device_id(udev: udev, id: c_str) -> udev_device;
+    pub fn udev_device_new_from_environment(udev: udev) -> udev_device;
     pub fn udev_device_unref(dev: udev_device) -> udev_device;
     pub fn udev_device_ref(dev: udev_device) -> udev_device;
 
@@ -57,8 +61,10 @@ extern {
     pub fn udev_device_get_is_initialized(dev: udev_device) -> c_int;
     pub fn udev_device_has_tag(dev: udev_device, tag: c_str) -> c_int;
     pub fn udev_device_get_action(dev: udev_device) -> c_str;
+    pub fn udev_device_get_devpath_old(dev: udev_device) -> c_str;
     pub fn udev_device_get_seqnum(dev: udev_device) -> u64;
     pub fn udev_device_get_usec_since_initialized(dev: udev_device) -> u64;
+    pub fn udev_device_get_property_value(dev: udev_device, key: c_str) -> c_str;
 
     // Device List
     pub fn udev_device_get_devlinks_list_entry(dev: udev_device) -> udev_list_entry;
@@ -102,8 +108,17 @@ extern {
     pub fn udev_monitor_receive_device(m: udev_monitor) -> udev_device;
     pub fn udev_monitor_unref(m: udev_monitor) -> udev_monitor;
     pub fn udev_monitor_enable_receiving(m: udev_monitor) -> c_int;
+    pub fn udev_monitor_set_receive_buffer_size(m: udev_monitor, size: c_int) -> c_int;
 
     pub fn udev_hwdb_new(u: udev) -> udev_list_entry;
     pub fn udev_hwdb_get_properties_list_entry(h: udev_hwdb, m: c_str) -> udev_list_entry;
     pub fn udev_hwdb_unref(h: udev_hwdb) -> udev_hwdb;
+
+    // Queue
+    pub fn udev_queue_new(u: udev) -> udev_queue;
+    pub fn udev_queue_unref(q: udev_queue) -> udev_queue;
+    pub fn udev_queue_get_udev_is_active(q: udev_queue) -> c_int;
+    pub fn udev_queue_get_queue_is_empty(q: udev_queue) -> c_int;
+    pub fn udev_queue_get_fd(q: udev_queue) -> c_int;
+    pub fn udev_queue_flush(q: udev_queue) -> c_int;
 }
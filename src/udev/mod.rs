@@ -15,11 +15,28 @@
 // You should have received a copy of the GNU Lesser General Public License
 // along with udev-rs; If not, see <http://www.gnu.org/licenses/>.
 
+#[cfg(feature = "libudev")]
 pub mod libudev_c;
 pub mod udev;
+#[cfg(feature = "libudev")]
 pub mod hwdb;
+pub mod builder;
 pub mod util;
+pub mod error;
+pub mod devnum;
 pub mod device;
+pub mod attribute;
+pub mod snapshot;
+pub mod db;
+pub mod filter;
 pub mod enumerator;
+pub mod event;
 pub mod monitor;
+#[cfg(feature = "sysfs")]
+pub mod netlink;
+pub mod queue;
+pub mod trigger;
+pub mod watcher;
 pub mod iterator;
+#[cfg(feature = "sysfs")]
+pub mod sysfs;
@@ -15,157 +15,412 @@
 // You should have received a copy of the GNU Lesser General Public License
 // along with udev-rs; If not, see <http://www.gnu.org/licenses/>.
 
-use std::ptr;
-use std::str::FromStr;
+#[cfg(feature = "libudev")]
+use std::{ptr, i32};
+use std::cell::Cell;
+use std::time::Duration;
+use std::os::unix::{AsRawFd, Fd};
 
+#[cfg(feature = "libudev")]
+use libc::{c_int, EAGAIN, EWOULDBLOCK, EINTR, ENOBUFS};
+
+use udev::{
+    error,
+    util,
+};
+#[cfg(feature = "libudev")]
 use udev::{
     device,
     libudev_c,
-    util,
 };
 use udev::udev::Udev;
 use udev::device::Device;
+use udev::error::Error;
+use udev::filter::Filter;
+use udev::event::Event;
+#[cfg(feature = "sysfs")]
+use udev::netlink;
+#[cfg(feature = "sysfs")]
+use udev::netlink::NetlinkMonitor;
+
+pub struct Monitor {
+    udev: Udev,
+    monitor: Inner,
+    // Whether or not the current filters have been installed and the socket bound.
+    receiving: Cell<bool>,
+    // Applied in userspace after libudev's socket filters.
+    filter: Option<Filter>,
+}
 
-pub struct Monitor<'u> {
-    udev: &'u Udev,
-    monitor: libudev_c::udev_monitor
+enum Inner {
+    #[cfg(feature = "libudev")]
+    Libudev(libudev_c::udev_monitor),
+    #[cfg(feature = "sysfs")]
+    Netlink(NetlinkMonitor),
 }
 
-#[deriving(Show)]
-pub enum Action {
-    Add,
-    Remove,
-    Change,
-    Move,
-    Online,
-    Offline,
-    Other(String)
+/// Whether a monitor blocks while waiting for events.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub enum Mode {
+    /// Receiving blocks until an event is available.
+    Blocking,
+    /// Receiving returns immediately if no event is available.
+    NonBlocking,
 }
 
-#[deriving(Show)]
-pub struct Event {
-    pub action: Action,
-    pub seqnum: u64
+#[doc(hidden)]
+pub struct MonitorIterator<'m> {
+    monitor: &'m Monitor,
+    done: bool,
 }
 
 #[doc(hidden)]
-pub struct MonitorIterator<'m, 'u: 'm> {
-    monitor: &'m Monitor<'u>
+pub struct DrainIterator<'m> {
+    monitor: &'m Monitor,
+    done: bool,
 }
 
+// Crate Private
+#[cfg(feature = "libudev")]
 pub unsafe fn monitor(udev: &Udev, monitor: libudev_c::udev_monitor) -> Monitor {
     Monitor {
-        udev: udev,
-        monitor: monitor
+        udev: udev.clone(),
+        monitor: Inner::Libudev(monitor),
+        receiving: Cell::new(false),
+        filter: None,
     }
 }
 
-impl<'u> Monitor<'u> {
+#[cfg(feature = "sysfs")]
+pub fn netlink_monitor(udev: &Udev, monitor: NetlinkMonitor) -> Monitor {
+    Monitor {
+        udev: udev.clone(),
+        monitor: Inner::Netlink(monitor),
+        receiving: Cell::new(false),
+        filter: None,
+    }
+}
+
+impl Monitor {
     /// Get the udev context.
     pub fn udev(&self) -> &Udev {
-        self.udev
+        &self.udev
     }
 
     /// Filter by subsystem.
     ///
     /// Exclude devices that don't match the specified subsystem or a previously specified
     /// subsystem.
-    pub fn filter_by_subsystem(self, subsystem: &str) -> Monitor<'u> {
-        subsystem.with_c_str(|subsystem| util::handle_error(unsafe {
-            libudev_c::udev_monitor_filter_add_match_subsystem_devtype(self.monitor, subsystem, ptr::null())
-        }));
-        self
+    pub fn filter_by_subsystem(mut self, subsystem: &str) -> Result<Monitor, Error> {
+        match self.monitor {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(monitor) => try!(subsystem.with_c_str(|c_subsystem| util::handle_error(unsafe {
+                libudev_c::udev_monitor_filter_add_match_subsystem_devtype(monitor, c_subsystem, ptr::null())
+            }, "udev_monitor_filter_add_match_subsystem_devtype", Some(subsystem)))),
+            #[cfg(feature = "sysfs")]
+            Inner::Netlink(ref mut monitor) => netlink::add_match_subsystem_devtype(monitor, subsystem, None),
+        }
+        self.receiving.set(false);
+        Ok(self)
     }
     /// Filter by subsystem/devtype combination.
     ///
     /// Exclude devices that don't match the specified subsystem/devtype combination or a
     /// previously specified subsystem/devtype combination (or any subsystem previously specified
     /// in a `filter_subsystem` invocation).
-    pub fn filter_by_subsystem_devtype(self, subsystem: &str, devtype: &str) -> Monitor<'u> {
-        subsystem.with_c_str(|subsystem| devtype.with_c_str(|devtype| util::handle_error(unsafe {
-            libudev_c::udev_monitor_filter_add_match_subsystem_devtype(self.monitor, subsystem, devtype)
-        })));
-        self
+    pub fn filter_by_subsystem_devtype(mut self, subsystem: &str, devtype: &str) -> Result<Monitor, Error> {
+        match self.monitor {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(monitor) => try!(subsystem.with_c_str(|c_subsystem| devtype.with_c_str(|c_devtype| {
+                util::handle_error(unsafe {
+                    libudev_c::udev_monitor_filter_add_match_subsystem_devtype(monitor, c_subsystem, c_devtype)
+                }, "udev_monitor_filter_add_match_subsystem_devtype", Some(subsystem))
+            }))),
+            #[cfg(feature = "sysfs")]
+            Inner::Netlink(ref mut monitor) => {
+                netlink::add_match_subsystem_devtype(monitor, subsystem, Some(devtype))
+            }
+        }
+        self.receiving.set(false);
+        Ok(self)
     }
     /// Filter by tag.
     ///
     /// Exclude devices that don't match the specified tag or a previously specified tag.
-    pub fn filter_by_tag(self, tag: &str) -> Monitor<'u> {
-        tag.with_c_str(|tag| util::handle_error(unsafe {
-            libudev_c::udev_monitor_filter_add_match_tag(self.monitor, tag)
-        }));
-        self
+    pub fn filter_by_tag(mut self, tag: &str) -> Result<Monitor, Error> {
+        match self.monitor {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(monitor) => try!(tag.with_c_str(|c_tag| util::handle_error(unsafe {
+                libudev_c::udev_monitor_filter_add_match_tag(monitor, c_tag)
+            }, "udev_monitor_filter_add_match_tag", Some(tag)))),
+            #[cfg(feature = "sysfs")]
+            Inner::Netlink(ref mut monitor) => netlink::add_match_tag(monitor, tag),
+        }
+        self.receiving.set(false);
+        Ok(self)
     }
 
-    /// Reset all filters on this monitor. No devices will be excluded.
-    pub fn clear_filters(self) -> Monitor<'u> {
-        util::handle_error(unsafe {
-            libudev_c::udev_monitor_filter_remove(self.monitor)
-        });
-        self
+    /// Filter with a userspace `Filter`.
+    ///
+    /// Events are checked against the filter after libudev's socket filters (see
+    /// `filter_by_subsystem` and `filter_by_tag`), which are far cheaper, so use those too where
+    /// possible. Events that don't match are dropped before they're returned by any receiving
+    /// method. Replaces any previously specified userspace filter.
+    pub fn filter_by(mut self, filter: Filter) -> Result<Monitor, Error> {
+        self.filter = Some(filter);
+        Ok(self)
+    }
+
+    /// Reset all filters (including the userspace filter) on this monitor. No devices will be
+    /// excluded.
+    pub fn clear_filters(mut self) -> Result<Monitor, Error> {
+        match self.monitor {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(monitor) => try!(util::handle_error(unsafe {
+                libudev_c::udev_monitor_filter_remove(monitor)
+            }, "udev_monitor_filter_remove", None)),
+            #[cfg(feature = "sysfs")]
+            Inner::Netlink(ref mut monitor) => netlink::remove_matches(monitor),
+        }
+        self.receiving.set(false);
+        self.filter = None;
+        Ok(self)
+    }
+
+    /// Set the size of the monitor's receive buffer (in bytes).
+    ///
+    /// A larger buffer reduces the chance of losing events (see `Error::Overflow`) when many
+    /// devices appear at once, e.g. when plugging in a docking station.
+    ///
+    /// # Error
+    ///
+    /// libudev forces the size past the system limit so this requires `CAP_NET_ADMIN`. Monitors of
+    /// the pure-Rust backend fall back to the capped size instead.
+    pub fn set_receive_buffer_size(&self, size: uint) -> Result<(), Error> {
+        match self.monitor {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(monitor) => {
+                let size = if size > i32::MAX as uint { i32::MAX as c_int } else { size as c_int };
+                util::handle_error(unsafe {
+                    libudev_c::udev_monitor_set_receive_buffer_size(monitor, size)
+                }, "udev_monitor_set_receive_buffer_size", None)
+            },
+            #[cfg(feature = "sysfs")]
+            Inner::Netlink(ref monitor) => monitor.set_receive_buffer_size(size),
+        }
     }
 
     /// Iterate over udev events.
     ///
     /// 1. The returned iterator will block on calls to next until their a device is available.
-    /// 2. The returned iterator will never end (next will never return None).
-    pub fn iter<'m>(&'m self) -> MonitorIterator<'m, 'u> {
-        util::handle_error(unsafe {
-            // Technically this mutates but we're single threaded anyways. Basically, having two
-            // iterators existing at the same time won't cause any problems because next() can't be
-            // called at the same time (single threaded).
-            libudev_c::udev_monitor_enable_receiving(self.monitor)
-        });
-        MonitorIterator::<'m, 'u> {
-            monitor: self
+    /// 2. The returned iterator yields an `Error::Overflow` whenever events have been lost and
+    ///    keeps going.
+    /// 3. The returned iterator only ends after yielding any other error.
+    ///
+    /// # Error
+    ///
+    /// This method will return an error if the monitor can't start receiving events.
+    pub fn iter<'m>(&'m self) -> Result<MonitorIterator<'m>, Error> {
+        try!(self.enable_receiving());
+        Ok(MonitorIterator::<'m> {
+            monitor: self,
+            done: false,
+        })
+    }
+
+    /// Receive an event without waiting.
+    ///
+    /// Returns `Ok(None)` if no event is available (or the next message was filtered out). This
+    /// never blocks, even on a blocking monitor.
+    pub fn try_receive(&self) -> Result<Option<(Event, Device)>, Error> {
+        try!(self.enable_receiving());
+        match util::is_readable(self.as_raw_fd()) {
+            Ok(true) => self.receive(),
+            Ok(false) => Ok(None),
+            Err(e) => Err(error::os(e, "poll", None))
+        }
+    }
+
+    /// Receive every queued event without blocking.
+    ///
+    /// The returned iterator ends once the monitor's socket has been emptied so it's safe to use
+    /// with edge-triggered event loops (epoll's `EPOLLET`, etc.). If events have been lost, the
+    /// iterator yields an `Error::Overflow` and keeps going. If receiving fails for any other
+    /// reason, the iterator yields the error and then ends. This works on both blocking and
+    /// non-blocking monitors.
+    pub fn drain<'m>(&'m self) -> Result<DrainIterator<'m>, Error> {
+        try!(self.enable_receiving());
+        Ok(DrainIterator::<'m> {
+            monitor: self,
+            done: false,
+        })
+    }
+
+    /// Receive an event, waiting at most `timeout`.
+    ///
+    /// Returns `Ok(None)` if no event arrived in time.
+    pub fn receive_timeout(&self, timeout: Duration) -> Result<Option<(Event, Device)>, Error> {
+        try!(self.enable_receiving());
+        let deadline = util::monotonic_now() + timeout;
+        loop {
+            let remaining = deadline - util::monotonic_now();
+            if remaining <= Duration::zero() {
+                return Ok(None);
+            }
+            match util::wait_readable(self.as_raw_fd(), Some(remaining)) {
+                Ok(true) => if let Some(event) = try!(self.receive()) {
+                    return Ok(Some(event));
+                },
+                Ok(false) => (),
+                Err(e) => return Err(error::os(e, "poll", None))
+            }
+        }
+    }
+
+    /// Start receiving events.
+    ///
+    /// This binds the monitor's socket and installs its filters. It's called automatically by
+    /// the receiving methods but calling it explicitly starts queuing events right away, e.g.
+    /// before scanning for existing devices with an `Enumerator`. Filters added afterwards are
+    /// installed by the next call to a receiving method.
+    pub fn enable_receiving(&self) -> Result<(), Error> {
+        if !self.receiving.get() {
+            match self.monitor {
+                #[cfg(feature = "libudev")]
+                Inner::Libudev(monitor) => try!(util::handle_error(unsafe {
+                    // Technically this mutates but we're single threaded anyways. Basically, having
+                    // two iterators existing at the same time won't cause any problems because
+                    // next() can't be called at the same time (single threaded).
+                    libudev_c::udev_monitor_enable_receiving(monitor)
+                }, "udev_monitor_enable_receiving", None)),
+                // The netlink socket is bound (and filters are applied) from the start.
+                #[cfg(feature = "sysfs")]
+                Inner::Netlink(_) => (),
+            }
+            self.receiving.set(true);
+        }
+        Ok(())
+    }
+
+    fn receive(&self) -> Result<Option<(Event, Device)>, Error> {
+        let received = match self.monitor {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(monitor) => match util::check_errno(|| unsafe {
+                libudev_c::udev_monitor_receive_device(monitor)
+            }) {
+                Ok(Some(raw)) => {
+                    let dev = unsafe { device::device(&self.udev, raw) };
+                    Some((event(&dev, raw, util::realtime_now()), dev))
+                },
+                // libudev returns NULL without setting errno when it drops a message (filtered,
+                // malformed, etc.).
+                Ok(None) => None,
+                Err(e) if e == EAGAIN || e == EWOULDBLOCK || e == EINTR => None,
+                Err(ENOBUFS) => return Err(Error::Overflow),
+                Err(e) => return Err(error::os(e, "udev_monitor_receive_device", None))
+            },
+            #[cfg(feature = "sysfs")]
+            Inner::Netlink(ref monitor) => try!(monitor.try_receive()),
+        };
+        match (&received, &self.filter) {
+            (&Some((_, ref dev)), &Some(ref filter)) if !filter.matches(dev) => return Ok(None),
+            _ => ()
+        }
+        Ok(received)
+    }
+}
+
+impl AsRawFd for Monitor {
+    fn as_raw_fd(&self) -> Fd {
+        match self.monitor {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(monitor) => unsafe { libudev_c::udev_monitor_get_fd(monitor) },
+            #[cfg(feature = "sysfs")]
+            Inner::Netlink(ref monitor) => monitor.as_raw_fd(),
         }
     }
 }
 
-#[unsafe_destructor]
-impl<'u> Drop for Monitor<'u> {
+#[cfg(feature = "libudev")]
+fn event(dev: &Device, raw: libudev_c::udev_device, received: Duration) -> Event {
+    Event {
+        action: from_str(unsafe {
+                    util::c_to_bytes(libudev_c::udev_device_get_action(raw))
+                }.map_or(String::new(), util::lossy).as_slice()).unwrap(),
+        seqnum: unsafe { libudev_c::udev_device_get_seqnum(raw) },
+        devpath_old: unsafe {
+            util::c_to_bytes(libudev_c::udev_device_get_devpath_old(raw))
+        }.map(|path| Path::new(path)),
+        driver: dev.driver_bytes().map(util::lossy),
+        synth_uuid: dev.property_value_bytes("SYNTH_UUID").map(util::lossy),
+        synth_args: dev.iter_properties_bytes().filter_map(|(key, value)| {
+            if key.starts_with(b"SYNTH_ARG_") {
+                Some((util::lossy(key.slice_from(10)), value.map_or(String::new(), util::lossy)))
+            } else {
+                None
+            }
+        }).collect(),
+        received: received,
+    }
+}
+
+impl Drop for Monitor {
     fn drop(&mut self) {
-        unsafe {
-            libudev_c::udev_monitor_unref(self.monitor);
+        match self.monitor {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(monitor) => unsafe { libudev_c::udev_monitor_unref(monitor); },
+            // Closes its own socket.
+            #[cfg(feature = "sysfs")]
+            Inner::Netlink(_) => (),
         }
     }
 }
 
-impl FromStr for Action {
-    fn from_str(s: &str) -> Option<Action> {
-        use self::Action::*;
-
-        match s {
-            "add"       => Some(Add),
-            "remove"    => Some(Remove),
-            "change"    => Some(Change),
-            "move"      => Some(Move),
-            "online"    => Some(Online),
-            "offline"   => Some(Offline),
-            _           => Some(Other(s.to_string())),
+impl<'m> Iterator<Result<(Event, Device), Error>> for DrainIterator<'m> {
+    fn next(&mut self) -> Option<Result<(Event, Device), Error>> {
+        while !self.done {
+            // Retries on EINTR: stopping early would strand queued events under edge-triggering.
+            match util::is_readable(self.monitor.as_raw_fd()) {
+                Ok(true) => match self.monitor.receive() {
+                    Ok(Some(event)) => return Some(Ok(event)),
+                    // Dropped message or interrupted; check whether there's more.
+                    Ok(None) => (),
+                    Err(Error::Overflow) => return Some(Err(Error::Overflow)),
+                    Err(e) => {
+                        self.done = true;
+                        return Some(Err(e));
+                    }
+                },
+                Ok(false) => self.done = true,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(error::os(e, "poll", None)));
+                }
+            }
         }
+        None
     }
 }
 
-impl<'m, 'u> Iterator<(Event, Device<'u>)> for MonitorIterator<'m, 'u> {
-    fn next(&mut self) -> Option<(Event, Device<'u>)> {
-        loop {
-            if let Ok(Some(dev)) = util::check_errno(|| unsafe {
-                libudev_c::udev_monitor_receive_device(self.monitor.monitor)
-            }) {
-                return Some((
-                    Event {
-                        action: from_str(unsafe {
-                                    util::c_to_str(libudev_c::udev_device_get_action(dev))
-                                }.unwrap()).unwrap(),
-                        seqnum: unsafe {
-                                    libudev_c::udev_device_get_seqnum(dev)
-                                }
-                    },
-                    unsafe { device::device(self.monitor.udev, dev) }
-                ));
+impl<'m> Iterator<Result<(Event, Device), Error>> for MonitorIterator<'m> {
+    fn next(&mut self) -> Option<Result<(Event, Device), Error>> {
+        while !self.done {
+            match self.monitor.receive() {
+                Ok(Some(event)) => return Some(Ok(event)),
+                Ok(None) => (),
+                Err(Error::Overflow) => return Some(Err(Error::Overflow)),
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+            // Don't spin on non-blocking monitors.
+            if let Err(e) = util::wait_readable(self.monitor.as_raw_fd(), None) {
+                self.done = true;
+                return Some(Err(error::os(e, "poll", None)));
             }
         }
+        None
     }
 }
 
@@ -0,0 +1,975 @@
+// This file is part of udev-rs.
+// 
+// Copyright 2014 Steven Allen <steven@stebalien.com>
+// 
+// udev-rs is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation; either version 2.1 of the License, or
+// (at your option) any later version.
+// 
+// udev-rs is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+// 
+// You should have received a copy of the GNU Lesser General Public License
+// along with udev-rs; If not, see <http://www.gnu.org/licenses/>.
+
+use std::{mem, str, i32};
+use std::num::Int;
+use std::time::Duration;
+use std::os::unix::{AsRawFd, Fd};
+
+use libc::{c_int, c_uint, c_ushort, c_void, size_t, ssize_t, EAGAIN, EWOULDBLOCK, EINTR, ENOBUFS};
+use libc::{O_NONBLOCK, F_GETFL, F_SETFL};
+use libc::{close, fcntl};
+
+use udev::{
+    device,
+    error,
+    util,
+    udev,
+};
+use udev::udev::Udev;
+use udev::error::Error;
+use udev::filter::Filter;
+use udev::event::{Event, Action};
+use udev::device::Device;
+use udev::sysfs::Sysfs;
+use udev::snapshot;
+use udev::snapshot::DeviceSnapshot;
+
+/// The multicast group a netlink monitor listens to.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub enum Group {
+    /// Raw events from the kernel, sent before udev has processed them.
+    Kernel,
+    /// Events sent by udev after it has processed them (see `Udev::monitor`).
+    Udev,
+}
+
+impl Group {
+    fn mask(&self) -> u32 {
+        match *self {
+            Group::Kernel   => 1,
+            Group::Udev     => 2,
+        }
+    }
+}
+
+/// The header libudev prefixes to the events it sends.
+///
+/// The hashes and bloom filter let receivers drop uninteresting events in a socket filter
+/// without parsing the properties.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub struct Header {
+    /// The MurmurHash2 of the device's subsystem (0 if it has none).
+    pub subsystem_hash: u32,
+    /// The MurmurHash2 of the device's devtype (0 if it has none).
+    pub devtype_hash: u32,
+    /// A bloom filter of the device's tags.
+    pub tag_bloom: u64,
+}
+
+/// A uevent as received from a netlink socket.
+///
+/// The kernel sends `action@devpath` followed by NUL separated `KEY=VALUE` properties. udev sends
+/// a binary header (see `Header`) followed by the properties.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub struct Message {
+    /// The libudev header (`None` for messages from the kernel).
+    pub header: Option<Header>,
+    /// The event's properties as (name, value) pairs.
+    pub properties: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+/// A uevent monitor that reads netlink directly instead of going through libudev.
+///
+/// This is what a `Monitor` of the pure-Rust backend receives from. Like libudev, it builds
+/// each event's `Device` from the event's properties; the device's attributes are read from
+/// sysfs when they're asked for. The socket is always non-blocking; `iter` and
+/// `receive_timeout` wait for events with `poll`.
+///
+/// Only messages sent by root are accepted and, on the kernel group, only those sent by the
+/// kernel itself.
+pub struct NetlinkMonitor {
+    udev: Udev,
+    // Where received devices are read from.
+    sysfs: Sysfs,
+    fd: Fd,
+    group: Group,
+    // Whether the socket is a netlink socket (rather than one passed to `from_fd`).
+    netlink: bool,
+    // The subsystem/devtype and tag matches of the `Monitor` wrapping this (see
+    // `Monitor::filter_by_subsystem`).
+    subsystems: Vec<(String, Option<String>)>,
+    tags: Vec<String>,
+    filter: Option<Filter>,
+}
+
+/// Sends uevents in the format `Monitor` and `NetlinkMonitor` receive.
+///
+/// Events sent to the udev group look like they came from udev (with a libudev header, see
+/// `Header`); events sent to the kernel group use the kernel's format. Sending to a netlink
+/// group requires `CAP_NET_ADMIN` and libudev only accepts events sent by root. For tests,
+/// use `from_fd` with one end of a unix socketpair and receive from the other end with
+/// `Udev::monitor_from_fd` (or `NetlinkMonitor::from_fd`).
+pub struct MonitorSender {
+    fd: Fd,
+    // The netlink group to send to (`None` for a connected socket passed to `from_fd`).
+    group: Option<Group>,
+}
+
+#[doc(hidden)]
+pub struct NetlinkIterator<'m> {
+    monitor: &'m NetlinkMonitor
+}
+
+// The header is laid out as `struct udev_monitor_netlink_header` in libudev.
+const PREFIX: &'static [u8] = b"libudev\0";
+const MAGIC: u32 = 0xfeedcafe;
+const HEADER_SIZE: uint = 40;
+
+// Large enough for any uevent (the kernel limits them to 2048 bytes of properties).
+const BUFFER_SIZE: uint = 8192;
+
+const AF_NETLINK: c_int = 16;
+const SOCK_DGRAM: c_int = 2;
+const SOCK_NONBLOCK: c_int = 0o4000;
+const SOCK_CLOEXEC: c_int = 0o2000000;
+const NETLINK_KOBJECT_UEVENT: c_int = 15;
+const SOL_SOCKET: c_int = 1;
+const SO_RCVBUF: c_int = 8;
+const SO_PASSCRED: c_int = 16;
+const SO_RCVBUFFORCE: c_int = 33;
+const SCM_CREDENTIALS: c_int = 2;
+const MSG_TRUNC: c_int = 0x20;
+
+#[repr(C)]
+struct sockaddr_nl {
+    nl_family: c_ushort,
+    nl_pad: c_ushort,
+    nl_pid: u32,
+    nl_groups: u32,
+}
+
+#[repr(C)]
+struct iovec {
+    iov_base: *mut c_void,
+    iov_len: size_t,
+}
+
+#[repr(C)]
+struct msghdr {
+    msg_name: *mut c_void,
+    msg_namelen: c_uint,
+    msg_iov: *mut iovec,
+    msg_iovlen: size_t,
+    msg_control: *mut c_void,
+    msg_controllen: size_t,
+    msg_flags: c_int,
+}
+
+#[repr(C)]
+struct cmsghdr {
+    cmsg_len: size_t,
+    cmsg_level: c_int,
+    cmsg_type: c_int,
+}
+
+#[repr(C)]
+struct ucred {
+    pid: i32,
+    uid: u32,
+    gid: u32,
+}
+
+extern {
+    fn socket(domain: c_int, ty: c_int, protocol: c_int) -> c_int;
+    fn bind(fd: c_int, addr: *const sockaddr_nl, len: c_uint) -> c_int;
+    fn setsockopt(fd: c_int, level: c_int, name: c_int, value: *const c_void, len: c_uint) -> c_int;
+    fn recvmsg(fd: c_int, msg: *mut msghdr, flags: c_int) -> ssize_t;
+}
+
+extern {
+    fn sendto(fd: c_int, buf: *const c_void, len: size_t, flags: c_int,
+              addr: *const sockaddr_nl, addrlen: c_uint) -> ssize_t;
+    fn geteuid() -> u32;
+}
+
+fn read_u32(buf: &[u8], offset: uint) -> u32 {
+    let mut bytes = [0u8, ..4];
+    for (dst, &src) in bytes.iter_mut().zip(buf.slice(offset, offset + 4).iter()) {
+        *dst = src;
+    }
+    unsafe { mem::transmute(bytes) }
+}
+
+fn u32_bytes(value: u32) -> [u8, ..4] {
+    unsafe { mem::transmute(value) }
+}
+
+// MurmurHash2 (with a seed of 0), as used by libudev to hash subsystems, devtypes and tags.
+fn string_hash32(data: &[u8]) -> u32 {
+    const M: u32 = 0x5bd1e995;
+
+    let mut h = data.len() as u32;
+    let mut rest = data;
+    while rest.len() >= 4 {
+        let mut k = read_u32(rest, 0);
+        k *= M;
+        k ^= k >> 24;
+        k *= M;
+        h *= M;
+        h ^= k;
+        rest = rest.slice_from(4);
+    }
+    if !rest.is_empty() {
+        for (i, &b) in rest.iter().enumerate() {
+            h ^= (b as u32) << (8 * i);
+        }
+        h *= M;
+    }
+    h ^= h >> 13;
+    h *= M;
+    h ^= h >> 15;
+    h
+}
+
+// Set the four bits of a 64 bit bloom filter selected by the hash of `data`.
+fn string_bloom64(data: &[u8]) -> u64 {
+    let hash = string_hash32(data);
+    range(0u, 4).fold(0u64, |bits, i| bits | 1u64 << ((hash >> (6 * i)) & 63) as uint)
+}
+
+fn parse_properties(buf: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+    buf.split(|&b| b == 0).filter_map(|entry| {
+        entry.iter().position(|&b| b == b'=').map(|i| {
+            (entry.slice_to(i).to_vec(), entry.slice_from(i + 1).to_vec())
+        })
+    }).collect()
+}
+
+// Crate Private
+pub fn set_option(fd: Fd, name: c_int, value: c_int, op: &'static str) -> Result<(), Error> {
+    let ret = unsafe {
+        setsockopt(fd, SOL_SOCKET, name, &value as *const c_int as *const c_void,
+                   mem::size_of::<c_int>() as c_uint)
+    };
+    if ret < 0 {
+        Err(error::os(util::get_errno(), op, None))
+    } else {
+        Ok(())
+    }
+}
+
+// Crate Private
+pub fn add_match_subsystem_devtype(monitor: &mut NetlinkMonitor, subsystem: &str, devtype: Option<&str>) {
+    monitor.subsystems.push((subsystem.to_string(), devtype.map(|devtype| devtype.to_string())));
+}
+
+pub fn add_match_tag(monitor: &mut NetlinkMonitor, tag: &str) {
+    monitor.tags.push(tag.to_string());
+}
+
+pub fn remove_matches(monitor: &mut NetlinkMonitor) {
+    monitor.subsystems.clear();
+    monitor.tags.clear();
+}
+
+// Set a socket's O_NONBLOCK flag.
+fn set_nonblocking(fd: Fd) -> Result<(), Error> {
+    let flags = unsafe { fcntl(fd, F_GETFL) };
+    if flags == -1 || unsafe { fcntl(fd, F_SETFL, flags | O_NONBLOCK) } == -1 {
+        Err(error::os(util::get_errno(), "fcntl", None))
+    } else {
+        Ok(())
+    }
+}
+
+// Compute the header for a device with the specified subsystem, devtype and tags.
+fn header<'a, I: Iterator<&'a [u8]>>(subsystem: Option<&[u8]>, devtype: Option<&[u8]>, tags: I) -> Header {
+    Header {
+        subsystem_hash: subsystem.map_or(0, string_hash32),
+        devtype_hash: devtype.map_or(0, string_hash32),
+        tag_bloom: tags.fold(0, |bits, tag| bits | string_bloom64(tag)),
+    }
+}
+
+// Create a libudev message carrying `properties` with `ACTION` set to `action`.
+fn message<'a, I: Iterator<(&'a [u8], &'a [u8])>>(action: &Action, header: Header, properties: I) -> Message {
+    let mut properties: Vec<(Vec<u8>, Vec<u8>)> = properties.filter(|&(key, _)| key != b"ACTION").map(|(key, value)| {
+        (key.to_vec(), value.to_vec())
+    }).collect();
+    properties.insert(0, (b"ACTION".to_vec(), action.as_str().as_bytes().to_vec()));
+    Message {
+        header: Some(header),
+        properties: properties,
+    }
+}
+
+impl Header {
+    /// Compute the header libudev sends with the specified device.
+    pub fn for_device(dev: &DeviceSnapshot) -> Header {
+        header(dev.subsystem_bytes(), dev.devtype_bytes(), dev.tags().iter().map(|tag| tag.as_slice()))
+    }
+}
+
+impl Message {
+    /// Create a libudev message for an event on the specified device.
+    ///
+    /// The message carries the device's properties with `ACTION` set to `action`.
+    pub fn from_device(action: &Action, dev: &DeviceSnapshot) -> Message {
+        message(action, Header::for_device(dev), dev.properties().iter().map(|&(ref key, ref value)| {
+            (key.as_slice(), value.as_ref().map(|value| value.as_slice()).unwrap_or(b""))
+        }))
+    }
+
+    /// Serialize the message (in the libudev format if it has a header, otherwise in the
+    /// kernel's format).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut properties = Vec::new();
+        for &(ref name, ref value) in self.properties.iter() {
+            properties.push_all(name.as_slice());
+            properties.push(b'=');
+            properties.push_all(value.as_slice());
+            properties.push(0);
+        }
+        let mut buf = Vec::new();
+        match self.header {
+            Some(ref header) => {
+                buf.push_all(PREFIX);
+                let fields = [
+                    MAGIC.to_be(),
+                    HEADER_SIZE as u32,
+                    HEADER_SIZE as u32,
+                    properties.len() as u32,
+                    header.subsystem_hash.to_be(),
+                    header.devtype_hash.to_be(),
+                    ((header.tag_bloom >> 32) as u32).to_be(),
+                    (header.tag_bloom as u32).to_be(),
+                ];
+                for &field in fields.iter() {
+                    buf.push_all(u32_bytes(field).as_slice());
+                }
+            },
+            None => {
+                buf.push_all(self.property_value_bytes("ACTION").unwrap_or(b""));
+                buf.push(b'@');
+                buf.push_all(self.property_value_bytes("DEVPATH").unwrap_or(b""));
+                buf.push(0);
+            }
+        }
+        buf.push_all(properties.as_slice());
+        buf
+    }
+
+    /// Parse a message in either the kernel or the libudev format.
+    ///
+    /// Returns `None` if the message is malformed. Properties without a `=` are skipped.
+    pub fn parse(buf: &[u8]) -> Option<Message> {
+        if buf.starts_with(PREFIX) {
+            if buf.len() < HEADER_SIZE || Int::from_be(read_u32(buf, 8)) != MAGIC {
+                return None;
+            }
+            let header_size = read_u32(buf, 12) as uint;
+            let offset = read_u32(buf, 16) as uint;
+            let len = read_u32(buf, 20) as uint;
+            if header_size < HEADER_SIZE || offset < header_size
+                || offset > buf.len() || len > buf.len() - offset {
+                return None;
+            }
+            let bloom_hi: u32 = Int::from_be(read_u32(buf, 32));
+            let bloom_lo: u32 = Int::from_be(read_u32(buf, 36));
+            Some(Message {
+                header: Some(Header {
+                    subsystem_hash: Int::from_be(read_u32(buf, 24)),
+                    devtype_hash: Int::from_be(read_u32(buf, 28)),
+                    tag_bloom: (bloom_hi as u64 << 32) | bloom_lo as u64,
+                }),
+                properties: parse_properties(buf.slice(offset, offset + len)),
+            })
+        } else {
+            // action@devpath
+            let end = match buf.iter().position(|&b| b == 0) {
+                Some(end) => end,
+                None => return None
+            };
+            if !buf.slice_to(end).contains(&b'@') {
+                return None;
+            }
+            Some(Message {
+                header: None,
+                properties: parse_properties(buf.slice_from(end + 1)),
+            })
+        }
+    }
+
+    /// Look up a property.
+    pub fn property_value_bytes(&self, name: &str) -> Option<&[u8]> {
+        snapshot::lookup(self.properties.as_slice(), name)
+    }
+
+    /// Convert the message into an event and a snapshot of the device it's about.
+    ///
+    /// Returns `None` if the `ACTION` or `DEVPATH` property is missing.
+    pub fn into_event(self, received: Duration) -> Option<(Event, DeviceSnapshot)> {
+        match self.to_event(received) {
+            Some(event) => DeviceSnapshot::from_properties(self.properties).map(|dev| (event, dev)),
+            None => None
+        }
+    }
+
+    // Returns `None` if the `ACTION` property is missing.
+    fn to_event(&self, received: Duration) -> Option<Event> {
+        let action = match self.property_value_bytes("ACTION").and_then(str::from_utf8) {
+            Some(action) => from_str(action).unwrap(),
+            None => return None
+        };
+        Some(Event {
+            action: action,
+            seqnum: self.property_value_bytes("SEQNUM")
+                .and_then(str::from_utf8)
+                .and_then(from_str)
+                .unwrap_or(0),
+            devpath_old: self.property_value_bytes("DEVPATH_OLD").map(|path| Path::new(path)),
+            driver: self.property_value_bytes("DRIVER").map(util::lossy),
+            synth_uuid: self.property_value_bytes("SYNTH_UUID").map(util::lossy),
+            synth_args: self.properties.iter().filter_map(|&(ref key, ref value)| {
+                if key.as_slice().starts_with(b"SYNTH_ARG_") {
+                    Some((util::lossy(key.slice_from(10)), util::lossy(value.as_slice())))
+                } else {
+                    None
+                }
+            }).collect(),
+            received: received,
+        })
+    }
+}
+
+impl NetlinkMonitor {
+    /// Open a netlink socket listening to the specified group.
+    ///
+    /// Received devices belong to `udev` and are read from its sysfs root (or from `/sys` if it's
+    /// a libudev context).
+    pub fn new(udev: &Udev, group: Group) -> Result<NetlinkMonitor, Error> {
+        let fd = unsafe {
+            socket(AF_NETLINK, SOCK_DGRAM | SOCK_NONBLOCK | SOCK_CLOEXEC, NETLINK_KOBJECT_UEVENT)
+        };
+        if fd < 0 {
+            return Err(error::os(util::get_errno(), "socket", None));
+        }
+        let addr = sockaddr_nl {
+            nl_family: AF_NETLINK as c_ushort,
+            nl_pad: 0,
+            nl_pid: 0,
+            nl_groups: group.mask(),
+        };
+        // Closes the socket on error.
+        let monitor = NetlinkMonitor::with_fd(udev, fd, group, true);
+
+        if unsafe { bind(fd, &addr, mem::size_of::<sockaddr_nl>() as c_uint) } < 0 {
+            return Err(error::os(util::get_errno(), "bind", None));
+        }
+        try!(set_option(fd, SO_PASSCRED, 1, "setsockopt"));
+        Ok(monitor)
+    }
+
+    /// Receive events from a unix datagram socket (e.g. one end of a socketpair).
+    ///
+    /// Takes ownership of the socket and makes it non-blocking. Events are expected in the format
+    /// of the specified group (see `MonitorSender`) and are accepted from root or from the
+    /// current user. Received devices belong to `udev` (see `new`).
+    pub fn from_fd(udev: &Udev, fd: Fd, group: Group) -> Result<NetlinkMonitor, Error> {
+        let monitor = NetlinkMonitor::with_fd(udev, fd, group, false);
+        try!(set_nonblocking(fd));
+        try!(set_option(fd, SO_PASSCRED, 1, "setsockopt"));
+        Ok(monitor)
+    }
+
+    fn with_fd(udev: &Udev, fd: Fd, group: Group, netlink: bool) -> NetlinkMonitor {
+        NetlinkMonitor {
+            udev: udev.clone(),
+            sysfs: udev::udev_get_sysfs(udev),
+            fd: fd,
+            group: group,
+            netlink: netlink,
+            subsystems: Vec::new(),
+            tags: Vec::new(),
+            filter: None,
+        }
+    }
+
+    /// Get the udev context.
+    pub fn udev(&self) -> &Udev {
+        &self.udev
+    }
+
+    /// Get the group this monitor listens to.
+    pub fn group(&self) -> Group {
+        self.group.clone()
+    }
+
+    /// Filter with a userspace `Filter`.
+    ///
+    /// Events that don't match are dropped before they're returned by any receiving method.
+    /// Replaces any previously specified filter.
+    pub fn filter_by(mut self, filter: Filter) -> NetlinkMonitor {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Set the size of the socket's receive buffer (in bytes).
+    ///
+    /// Sizes past the system limit require `CAP_NET_ADMIN`; without it, the size is capped.
+    pub fn set_receive_buffer_size(&self, size: uint) -> Result<(), Error> {
+        let size = if size > i32::MAX as uint { i32::MAX as c_int } else { size as c_int };
+        set_option(self.fd, SO_RCVBUFFORCE, size, "setsockopt").or_else(|_| {
+            set_option(self.fd, SO_RCVBUF, size, "setsockopt")
+        })
+    }
+
+    /// Iterate over events.
+    ///
+    /// The iterator blocks until an event is available and never ends. It yields an
+    /// `Error::Overflow` whenever events have been lost.
+    pub fn iter<'m>(&'m self) -> NetlinkIterator<'m> {
+        NetlinkIterator::<'m> {
+            monitor: self
+        }
+    }
+
+    /// Receive an event without waiting.
+    ///
+    /// Returns `Ok(None)` if no event is available or the next message was dropped (filtered,
+    /// malformed or from an untrusted sender). Returns `Err(Error::Overflow)` if events were
+    /// lost, either because the socket's buffer overflowed or because a message was too large.
+    pub fn try_receive(&self) -> Result<Option<(Event, Device)>, Error> {
+        let mut buf = [0u8, ..BUFFER_SIZE];
+        let len = match try!(self.receive_message(buf.as_mut_slice())) {
+            Some(len) => len,
+            None => return Ok(None)
+        };
+        let message = match Message::parse(buf.slice_to(len)) {
+            Some(message) => message,
+            None => return Ok(None)
+        };
+        // Kernel messages are only trusted from the kernel group and vice versa.
+        if message.header.is_some() != (self.group == Group::Udev) {
+            return Ok(None);
+        }
+        let event = match message.to_event(util::realtime_now()) {
+            Some(event) => event,
+            None => return Ok(None)
+        };
+        let dev = match self.sysfs.from_properties(message.properties) {
+            Some(dev) => device::sysfs_device(&self.udev, dev),
+            None => return Ok(None)
+        };
+        if self.matches(&dev) {
+            Ok(Some((event, dev)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Receive an event, waiting at most `timeout`.
+    ///
+    /// Returns `Ok(None)` if no event arrived in time.
+    pub fn receive_timeout(&self, timeout: Duration) -> Result<Option<(Event, Device)>, Error> {
+        let deadline = util::monotonic_now() + timeout;
+        loop {
+            let remaining = deadline - util::monotonic_now();
+            if remaining <= Duration::zero() {
+                return Ok(None);
+            }
+            match util::wait_readable(self.fd, Some(remaining)) {
+                Ok(true) => if let Some(event) = try!(self.try_receive()) {
+                    return Ok(Some(event));
+                },
+                Ok(false) => (),
+                Err(e) => return Err(error::os(e, "poll", None))
+            }
+        }
+    }
+
+    // Like libudev, a device must match one of the subsystem/devtype matches (if any) and one of
+    // the tag matches (if any).
+    fn matches(&self, dev: &Device) -> bool {
+        let subsystem = self.subsystems.is_empty() || self.subsystems.iter().any(|&(ref subsystem, ref devtype)| {
+            dev.subsystem() == Some(subsystem.as_slice())
+                && devtype.as_ref().map_or(true, |devtype| dev.devtype() == Some(devtype.as_slice()))
+        });
+        let tag = self.tags.is_empty() || self.tags.iter().any(|tag| dev.has_tag(tag.as_slice()));
+        subsystem && tag && self.filter.as_ref().map_or(true, |filter| filter.matches(dev))
+    }
+
+    // Receive a message into `buf`, checking who sent it. Returns the message's length.
+    fn receive_message(&self, buf: &mut [u8]) -> Result<Option<uint>, Error> {
+        let mut addr = sockaddr_nl { nl_family: 0, nl_pad: 0, nl_pid: 0, nl_groups: 0 };
+        let mut iov = iovec {
+            iov_base: buf.as_mut_ptr() as *mut c_void,
+            iov_len: buf.len() as size_t,
+        };
+        // Room for one SCM_CREDENTIALS message, suitably aligned.
+        let mut control = [0u64, ..8];
+        let mut msg = msghdr {
+            msg_name: &mut addr as *mut sockaddr_nl as *mut c_void,
+            msg_namelen: mem::size_of::<sockaddr_nl>() as c_uint,
+            msg_iov: &mut iov,
+            msg_iovlen: 1,
+            msg_control: control.as_mut_ptr() as *mut c_void,
+            msg_controllen: mem::size_of_val(&control) as size_t,
+            msg_flags: 0,
+        };
+
+        let len = unsafe { recvmsg(self.fd, &mut msg, 0) };
+        if len < 0 {
+            return match util::get_errno() {
+                e if e == EAGAIN || e == EWOULDBLOCK || e == EINTR => Ok(None),
+                ENOBUFS => Err(Error::Overflow),
+                e => Err(error::os(e, "recvmsg", None))
+            };
+        }
+        // The message didn't fit so its event is lost.
+        if msg.msg_flags & MSG_TRUNC != 0 {
+            return Err(Error::Overflow);
+        }
+        // Drop unicast messages and, on the kernel group, messages not sent by the kernel.
+        if self.netlink && (addr.nl_groups == 0 || (self.group == Group::Kernel && addr.nl_pid != 0)) {
+            return Ok(None);
+        }
+        match credentials(&msg, control.as_slice()) {
+            Some(cred) if cred.uid == 0 => Ok(Some(len as uint)),
+            Some(cred) if !self.netlink && cred.uid == unsafe { geteuid() } => Ok(Some(len as uint)),
+            _ => Ok(None)
+        }
+    }
+}
+
+// Find the sender's credentials (`SCM_CREDENTIALS`) in a received message.
+fn credentials<'a>(msg: &msghdr, control: &'a [u64]) -> Option<&'a ucred> {
+    let align = mem::size_of::<size_t>();
+    let header_len = (mem::size_of::<cmsghdr>() + align - 1) & !(align - 1);
+    if (msg.msg_controllen as uint) < header_len {
+        return None;
+    }
+    let cmsg = unsafe { &*(control.as_ptr() as *const cmsghdr) };
+    if cmsg.cmsg_level != SOL_SOCKET || cmsg.cmsg_type != SCM_CREDENTIALS
+        || (cmsg.cmsg_len as uint) < header_len + mem::size_of::<ucred>() {
+        return None;
+    }
+    Some(unsafe { &*((control.as_ptr() as *const u8).offset(header_len as int) as *const ucred) })
+}
+
+impl AsRawFd for NetlinkMonitor {
+    fn as_raw_fd(&self) -> Fd {
+        self.fd
+    }
+}
+
+impl Drop for NetlinkMonitor {
+    fn drop(&mut self) {
+        unsafe {
+            close(self.fd);
+        }
+    }
+}
+
+impl MonitorSender {
+    /// Open a netlink socket sending to the specified group.
+    pub fn new(group: Group) -> Result<MonitorSender, Error> {
+        let fd = unsafe { socket(AF_NETLINK, SOCK_DGRAM | SOCK_CLOEXEC, NETLINK_KOBJECT_UEVENT) };
+        if fd < 0 {
+            return Err(error::os(util::get_errno(), "socket", None));
+        }
+        Ok(MonitorSender {
+            fd: fd,
+            group: Some(group),
+        })
+    }
+
+    /// Send events (in the udev group's format) on a connected unix datagram socket.
+    ///
+    /// Takes ownership of the socket.
+    pub fn from_fd(fd: Fd) -> MonitorSender {
+        MonitorSender {
+            fd: fd,
+            group: None,
+        }
+    }
+
+    /// Send an event on the specified device.
+    pub fn send(&self, action: &Action, dev: &DeviceSnapshot) -> Result<(), Error> {
+        self.send_event(Message::from_device(action, dev))
+    }
+
+    /// Send an event on the specified device.
+    ///
+    /// Like libudev, the message is built from the device's properties (and its subsystem,
+    /// devtype and tags for the header); none of its sysfs attributes are read.
+    pub fn send_device(&self, action: &Action, dev: &Device) -> Result<(), Error> {
+        let header = header(dev.subsystem_bytes(), dev.devtype_bytes(), dev.iter_tags_bytes());
+        self.send_event(message(action, header, dev.iter_properties_bytes().map(|(key, value)| {
+            (key, value.unwrap_or(b""))
+        })))
+    }
+
+    // Send an event message in the format of the socket's group.
+    fn send_event(&self, mut message: Message) -> Result<(), Error> {
+        if self.group == Some(Group::Kernel) {
+            message.header = None;
+        }
+        self.send_message(&message)
+    }
+
+    /// Send a message as-is.
+    pub fn send_message(&self, message: &Message) -> Result<(), Error> {
+        let buf = message.to_bytes();
+        let addr = sockaddr_nl {
+            nl_family: AF_NETLINK as c_ushort,
+            nl_pad: 0,
+            nl_pid: 0,
+            nl_groups: self.group.as_ref().map_or(0, |group| group.mask()),
+        };
+        let (addr, addrlen) = match self.group {
+            Some(_) => (&addr as *const sockaddr_nl, mem::size_of::<sockaddr_nl>() as c_uint),
+            None => (0 as *const sockaddr_nl, 0)
+        };
+        let sent = unsafe {
+            sendto(self.fd, buf.as_ptr() as *const c_void, buf.len() as size_t, 0, addr, addrlen)
+        };
+        if sent < 0 {
+            Err(error::os(util::get_errno(), "sendto", None))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl AsRawFd for MonitorSender {
+    fn as_raw_fd(&self) -> Fd {
+        self.fd
+    }
+}
+
+impl Drop for MonitorSender {
+    fn drop(&mut self) {
+        unsafe {
+            close(self.fd);
+        }
+    }
+}
+
+impl<'m> Iterator<Result<(Event, Device), Error>> for NetlinkIterator<'m> {
+    fn next(&mut self) -> Option<Result<(Event, Device), Error>> {
+        loop {
+            match self.monitor.try_receive() {
+                Ok(Some(event)) => return Some(Ok(event)),
+                Ok(None) => (),
+                Err(e) => return Some(Err(e)),
+            }
+            if let Err(e) = util::wait_readable(self.monitor.fd, None) {
+                return Some(Err(error::os(e, "poll", None)));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::num::Int;
+    use std::time::Duration;
+
+    use udev::util;
+    use udev::event::Action;
+    use udev::udev::Udev;
+    use udev::snapshot::DeviceSnapshot;
+    use super::{NetlinkMonitor, MonitorSender, Group, Message, Header, MAGIC};
+    use super::{u32_bytes, string_hash32, string_bloom64};
+
+    static KERNEL: &'static [u8] = b"add@/devices/virtual/block/loop0\0ACTION=add\0\
+DEVPATH=/devices/virtual/block/loop0\0SUBSYSTEM=block\0MAJOR=7\0MINOR=0\0DEVNAME=loop0\0\
+DEVTYPE=disk\0SEQNUM=2345\0";
+
+    static PROPERTIES: &'static [u8] = b"ACTION=move\0DEVPATH=/devices/virtual/net/wlan0\0\
+DEVPATH_OLD=/devices/virtual/net/wlp3s0\0SUBSYSTEM=net\0INTERFACE=wlan0\0SEQNUM=77\0\
+USEC_INITIALIZED=1000\0TAGS=:systemd:\0SYNTH_UUID=1b4e28ba-2fa1-11d2-883f-0016d3cca427\0\
+SYNTH_ARG_REASON=test\0";
+
+    fn device() -> DeviceSnapshot {
+        util::snapshot("/devices/virtual/block/loop0", "block", &[
+            ("ACTION", "add"),
+            ("DEVTYPE", "disk"),
+            ("DEVNAME", "/dev/loop0"),
+            ("SEQNUM", "12"),
+            ("TAGS", ":systemd:seat:"),
+        ])
+    }
+
+    // Build a libudev message the way udev lays it out.
+    fn udev_message(properties: &[u8]) -> Vec<u8> {
+        let mut buf = b"libudev\0".to_vec();
+        buf.push_all(u32_bytes(MAGIC.to_be()).as_slice());
+        buf.push_all(u32_bytes(40).as_slice());
+        buf.push_all(u32_bytes(40).as_slice());
+        buf.push_all(u32_bytes(properties.len() as u32).as_slice());
+        buf.push_all(u32_bytes(0x12345678u32.to_be()).as_slice());
+        buf.push_all(u32_bytes(0).as_slice());
+        buf.push_all(u32_bytes(0x01020304u32.to_be()).as_slice());
+        buf.push_all(u32_bytes(0x05060708u32.to_be()).as_slice());
+        buf.push_all(properties);
+        buf
+    }
+
+    #[test]
+    fn test_kernel_message() {
+        let message = Message::parse(KERNEL).unwrap();
+        assert!(message.header.is_none());
+        assert_eq!(message.property_value_bytes("SUBSYSTEM"), Some(b"block"));
+
+        let (event, dev) = message.into_event(Duration::zero()).unwrap();
+        assert_eq!(event.action, Action::Add);
+        assert_eq!(event.seqnum, 2345);
+        assert!(event.devpath_old.is_none());
+        assert_eq!(dev.syspath(), &Path::new("/sys/devices/virtual/block/loop0"));
+        assert_eq!(dev.devnode(), Some(&Path::new("/dev/loop0")));
+        assert_eq!(dev.devtype(), Some("disk"));
+        assert!(!dev.is_initialized());
+    }
+
+    #[test]
+    fn test_udev_message() {
+        let message = Message::parse(udev_message(PROPERTIES).as_slice()).unwrap();
+        assert_eq!(message.header, Some(Header {
+            subsystem_hash: 0x12345678,
+            devtype_hash: 0,
+            tag_bloom: 0x0102030405060708,
+        }));
+
+        let (event, dev) = message.into_event(Duration::seconds(1)).unwrap();
+        assert_eq!(event.action, Action::Move);
+        assert_eq!(event.seqnum, 77);
+        assert_eq!(event.devpath_old, Some(Path::new("/devices/virtual/net/wlp3s0")));
+        assert_eq!(event.synth_uuid.as_ref().map(|s| s.as_slice()), Some("1b4e28ba-2fa1-11d2-883f-0016d3cca427"));
+        assert_eq!(event.synth_args, vec![("REASON".to_string(), "test".to_string())]);
+        assert_eq!(event.received, Duration::seconds(1));
+        assert_eq!(dev.sysname(), Some("wlan0"));
+        assert!(dev.is_initialized());
+        assert!(dev.has_tag("systemd"));
+    }
+
+    #[test]
+    fn test_malformed() {
+        // No action@devpath.
+        assert!(Message::parse(b"ACTION=add\0DEVPATH=/devices/foo\0").is_none());
+        assert!(Message::parse(b"add@/devices/foo").is_none());
+
+        // Bad magic.
+        let mut buf = udev_message(PROPERTIES);
+        buf.as_mut_slice()[8] = 0;
+        assert!(Message::parse(buf.as_slice()).is_none());
+
+        // Truncated.
+        let buf = udev_message(PROPERTIES);
+        assert!(Message::parse(buf.slice_to(buf.len() - 1)).is_none());
+        assert!(Message::parse(buf.slice_to(20)).is_none());
+
+        // Missing properties.
+        let message = Message::parse(b"add@/devices/foo\0ACTION=add\0SEQNUM=1\0").unwrap();
+        assert!(message.into_event(Duration::zero()).is_none());
+        let message = Message::parse(b"add@/devices/foo\0DEVPATH=/devices/foo\0garbage\0").unwrap();
+        assert_eq!(message.properties.len(), 1);
+        assert!(message.into_event(Duration::zero()).is_none());
+    }
+
+    #[test]
+    fn test_hash() {
+        assert_eq!(string_hash32(b""), 0);
+        assert_eq!(string_hash32(b"block"), 0xf0031db7);
+        assert_eq!(string_hash32(b"disk"), 0x7bcbc5ee);
+        assert_eq!(string_bloom64(b"systemd"), 0x0200040010800000);
+        assert_eq!(Header::for_device(&device()), Header {
+            subsystem_hash: 0xf0031db7,
+            devtype_hash: 0x7bcbc5ee,
+            tag_bloom: 0x0208040010c00001,
+        });
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let message = Message::from_device(&Action::Change, &device());
+        assert_eq!(message.property_value_bytes("ACTION"), Some(b"change"));
+        assert_eq!(message.properties.iter().filter(|&&(ref key, _)| key.as_slice() == b"ACTION").count(), 1);
+        assert_eq!(Message::parse(message.to_bytes().as_slice()), Some(message.clone()));
+
+        let kernel = Message { header: None, ..message };
+        let buf = kernel.to_bytes();
+        assert!(buf.as_slice().starts_with(b"change@/devices/virtual/block/loop0\0ACTION=change\0"));
+        assert_eq!(Message::parse(buf.as_slice()), Some(kernel));
+    }
+
+    #[test]
+    fn test_loopback() {
+        let (receiver, sender) = util::socket_pair();
+        let udev = Udev::new();
+        let monitor = NetlinkMonitor::from_fd(&udev, receiver, Group::Udev).unwrap();
+        let sender = MonitorSender::from_fd(sender);
+        let dev = device();
+
+        assert!(monitor.try_receive().unwrap().is_none());
+        sender.send(&Action::Change, &dev).unwrap();
+        let (event, received) = monitor.receive_timeout(Duration::seconds(5)).unwrap().unwrap();
+        assert_eq!(event.action, Action::Change);
+        assert_eq!(event.seqnum, 12);
+        assert_eq!(&received.syspath(), dev.syspath());
+        assert_eq!(received.devnode().as_ref(), dev.devnode());
+        assert!(received.has_tag("systemd") && received.has_tag("seat"));
+        assert_eq!(received.property_value("ACTION"), Some("change"));
+
+        // Kernel messages aren't accepted on the udev group.
+        let kernel = Message { header: None, ..Message::from_device(&Action::Add, &dev) };
+        sender.send_message(&kernel).unwrap();
+        assert!(monitor.receive_timeout(Duration::milliseconds(100)).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_monitor_loopback() {
+        use std::io::{File, TempDir, USER_RWX};
+        use std::io::fs;
+        use udev::builder::UdevBuilder;
+
+        let dir = TempDir::new("udev-rs-netlink").unwrap();
+        let loop0 = dir.path().join("sys/devices/virtual/block/loop0");
+        fs::mkdir_recursive(&loop0, USER_RWX).unwrap();
+        fs::mkdir_recursive(&dir.path().join("sys/class/block"), USER_RWX).unwrap();
+        File::create(&loop0.join("uevent")).write_str("MAJOR=7\nMINOR=0\nDEVNAME=loop0\nDEVTYPE=disk\n").unwrap();
+        fs::symlink(&Path::new("../../../../class/block"), &loop0.join("subsystem")).unwrap();
+        let udev = UdevBuilder::new()
+            .sysfs_root(dir.path().join("sys"))
+            .dev_root(dir.path().join("dev"))
+            .udev_run_dir(dir.path().join("run"))
+            .build()
+            .unwrap();
+        let dev = udev.device(&loop0).unwrap();
+
+        let (receiver, sender) = util::socket_pair();
+        let monitor = udev.monitor_from_fd(receiver).unwrap().filter_by_subsystem("block").unwrap();
+        let sender = MonitorSender::from_fd(sender);
+
+        // Devices are sent from their properties and received under the context's roots.
+        assert!(monitor.try_receive().unwrap().is_none());
+        sender.send_device(&Action::Add, &dev).unwrap();
+        let (event, received) = monitor.receive_timeout(Duration::seconds(5)).unwrap().unwrap();
+        assert_eq!(event.action, Action::Add);
+        assert_eq!(received.syspath(), loop0);
+        assert_eq!(received.subsystem(), Some("block"));
+        assert_eq!(received.devtype(), Some("disk"));
+        assert_eq!(received.devnode(), Some(dir.path().join("dev/loop0")));
+
+        // The monitor's filters apply to sent events.
+        let tty = util::snapshot("/devices/virtual/tty/tty0", "tty", &[]);
+        sender.send(&Action::Add, &tty).unwrap();
+        assert!(monitor.receive_timeout(Duration::milliseconds(100)).unwrap().is_none());
+    }
+}
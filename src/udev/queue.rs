@@ -0,0 +1,216 @@
+// This file is part of udev-rs.
+// 
+// Copyright 2014 Steven Allen <steven@stebalien.com>
+// 
+// udev-rs is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation; either version 2.1 of the License, or
+// (at your option) any later version.
+// 
+// udev-rs is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+// 
+// You should have received a copy of the GNU Lesser General Public License
+// along with udev-rs; If not, see <http://www.gnu.org/licenses/>.
+
+use std::time::Duration;
+use std::os::unix::Fd;
+#[cfg(feature = "sysfs")]
+use std::cell::Cell;
+
+#[cfg(feature = "sysfs")]
+use libc::{c_int, c_char, c_void, size_t, ssize_t, close, EAGAIN, EINTR};
+
+#[cfg(feature = "libudev")]
+use udev::libudev_c;
+use udev::{
+    error,
+    util,
+};
+use udev::udev::Udev;
+use udev::error::Error;
+
+/// The udev event queue.
+///
+/// Reports whether the udev daemon is running and whether it's still processing events.
+pub struct Queue {
+    udev: Udev,
+    queue: Inner,
+}
+
+enum Inner {
+    #[cfg(feature = "libudev")]
+    Libudev(libudev_c::udev_queue),
+    // Like libudev, watch udev's runtime directory for the removal of the queue file. The
+    // inotify descriptor is only created when it's asked for.
+    #[cfg(feature = "sysfs")]
+    Sysfs {
+        run_dir: Path,
+        fd: Cell<Option<Fd>>,
+    },
+}
+
+#[cfg(feature = "sysfs")]
+const IN_NONBLOCK: c_int = 0o4000;
+#[cfg(feature = "sysfs")]
+const IN_CLOEXEC: c_int = 0o2000000;
+#[cfg(feature = "sysfs")]
+const IN_DELETE: u32 = 0x200;
+
+#[cfg(feature = "sysfs")]
+extern {
+    fn inotify_init1(flags: c_int) -> c_int;
+    fn inotify_add_watch(fd: c_int, path: *const c_char, mask: u32) -> c_int;
+    fn read(fd: c_int, buf: *mut c_void, count: size_t) -> ssize_t;
+}
+
+// Crate Private
+#[cfg(feature = "libudev")]
+pub unsafe fn queue(udev: &Udev, queue: libudev_c::udev_queue) -> Queue {
+    Queue { udev: udev.clone(), queue: Inner::Libudev(queue) }
+}
+
+#[cfg(feature = "sysfs")]
+pub fn sysfs_queue(udev: &Udev, run_dir: &Path) -> Queue {
+    Queue {
+        udev: udev.clone(),
+        queue: Inner::Sysfs { run_dir: run_dir.clone(), fd: Cell::new(None) },
+    }
+}
+
+impl Queue {
+    /// Get the udev context.
+    pub fn udev(&self) -> &Udev {
+        &self.udev
+    }
+
+    /// Check whether the udev daemon is running.
+    pub fn is_active(&self) -> bool {
+        match self.queue {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(queue) => unsafe { libudev_c::udev_queue_get_udev_is_active(queue) != 0 },
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs { ref run_dir, .. } => run_dir.join("control").exists(),
+        }
+    }
+
+    /// Check whether the udev daemon has no queued or running events.
+    pub fn is_empty(&self) -> bool {
+        match self.queue {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(queue) => unsafe { libudev_c::udev_queue_get_queue_is_empty(queue) != 0 },
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs { ref run_dir, .. } => !run_dir.join("queue").exists(),
+        }
+    }
+
+    /// Check whether there's nothing to wait for: either the queue is empty or the udev daemon
+    /// isn't running (so no events will be processed).
+    pub fn is_idle(&self) -> bool {
+        !self.is_active() || self.is_empty()
+    }
+
+    /// Get a file descriptor that becomes readable when the queue changes.
+    ///
+    /// After the descriptor becomes readable, call `flush` and check the queue's state again.
+    /// The descriptor is owned by the queue.
+    pub fn fd(&self) -> Result<Fd, Error> {
+        match self.queue {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(queue) => match unsafe { libudev_c::udev_queue_get_fd(queue) } {
+                fd if fd >= 0 => Ok(fd),
+                e => Err(error::os(-e, "udev_queue_get_fd", None))
+            },
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs { ref run_dir, ref fd } => {
+                if let Some(fd) = fd.get() {
+                    return Ok(fd);
+                }
+                let new_fd = unsafe { inotify_init1(IN_NONBLOCK | IN_CLOEXEC) };
+                if new_fd < 0 {
+                    return Err(error::os(util::get_errno(), "inotify_init1", None));
+                }
+                if run_dir.with_c_str(|path| unsafe { inotify_add_watch(new_fd, path, IN_DELETE) }) < 0 {
+                    let errno = util::get_errno();
+                    unsafe { close(new_fd) };
+                    return Err(error::os(errno, "inotify_add_watch", run_dir.as_str()));
+                }
+                fd.set(Some(new_fd));
+                Ok(new_fd)
+            }
+        }
+    }
+
+    /// Clear the pending notifications on the queue's file descriptor.
+    pub fn flush(&self) -> Result<(), Error> {
+        match self.queue {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(queue) => util::handle_error(unsafe {
+                libudev_c::udev_queue_flush(queue)
+            }, "udev_queue_flush", None),
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs { ref fd, .. } => match fd.get() {
+                Some(fd) => {
+                    let mut buf = [0u8, ..4096];
+                    loop {
+                        if unsafe { read(fd, buf.as_mut_ptr() as *mut c_void, buf.len() as size_t) } < 0 {
+                            return match util::get_errno() {
+                                EINTR => continue,
+                                EAGAIN => Ok(()),
+                                e => Err(error::os(e, "read", None))
+                            };
+                        }
+                    }
+                },
+                None => Ok(())
+            }
+        }
+    }
+    /// Wait until the queue is idle (like `udevadm settle`).
+    ///
+    /// Unlike `udevadm settle`, this doesn't ask the udev daemon to pick up events the kernel has
+    /// sent but it hasn't read yet. Events triggered right before calling this may not be queued
+    /// yet; use `Trigger::wait` to wait for specific triggered events instead.
+    ///
+    /// # Error
+    ///
+    /// Returns an `Error::Timeout` if the queue is still busy after `timeout`.
+    pub fn settle(&self, timeout: Duration) -> Result<(), Error> {
+        // Without the udev daemon (or /run/udev), the queue is trivially idle and there may be
+        // nothing to watch.
+        if self.is_idle() {
+            return Ok(());
+        }
+        let deadline = util::monotonic_now() + timeout;
+        let fd = try!(self.fd());
+        loop {
+            if self.is_idle() {
+                return Ok(());
+            }
+            let remaining = deadline - util::monotonic_now();
+            if remaining <= Duration::zero() {
+                return Err(Error::Timeout { op: "settle" });
+            }
+            match util::wait_readable(fd, Some(remaining)) {
+                Ok(true) => try!(self.flush()),
+                Ok(false) => (),
+                Err(e) => return Err(error::os(e, "poll", None))
+            }
+        }
+    }
+}
+
+impl Drop for Queue {
+    fn drop(&mut self) {
+        match self.queue {
+            #[cfg(feature = "libudev")]
+            Inner::Libudev(queue) => unsafe { libudev_c::udev_queue_unref(queue); },
+            #[cfg(feature = "sysfs")]
+            Inner::Sysfs { ref fd, .. } => if let Some(fd) = fd.get() {
+                unsafe { close(fd) };
+            }
+        }
+    }
+}
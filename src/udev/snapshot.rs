@@ -0,0 +1,304 @@
+// This file is part of udev-rs.
+// 
+// Copyright 2014 Steven Allen <steven@stebalien.com>
+// 
+// udev-rs is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation; either version 2.1 of the License, or
+// (at your option) any later version.
+// 
+// udev-rs is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+// 
+// You should have received a copy of the GNU Lesser General Public License
+// along with udev-rs; If not, see <http://www.gnu.org/licenses/>.
+
+use std::{fmt, str};
+
+use udev::udev::Udev;
+use udev::device::Device;
+use udev::devnum::Devnum;
+
+/// An owned copy of a device.
+///
+/// A snapshot copies everything udev knows about a device (its properties, tags and devlinks)
+/// out of a `Device`, plus its readable sysfs attributes when asked to (see
+/// `Device::snapshot_with_attributes`). Unlike a `Device`, a snapshot doesn't refer to a
+/// udev context so it can be sent to other threads. Use `device` to look the device up again
+/// from a context owned by the receiving thread.
+#[deriving(Clone, PartialEq, Eq)]
+pub struct DeviceSnapshot {
+    syspath: Path,
+    devpath: Vec<u8>,
+    sysname: Vec<u8>,
+    subsystem: Option<Vec<u8>>,
+    devtype: Option<Vec<u8>>,
+    driver: Option<Vec<u8>>,
+    devnode: Option<Path>,
+    devnum: Option<Devnum>,
+    is_initialized: bool,
+    // Like libudev, a property may have no value.
+    properties: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+    attributes: Vec<(Vec<u8>, Vec<u8>)>,
+    tags: Vec<Vec<u8>>,
+    devlinks: Vec<Path>,
+}
+
+// Crate Private
+pub fn snapshot(dev: &Device, with_attributes: bool) -> DeviceSnapshot {
+    DeviceSnapshot {
+        syspath: dev.syspath(),
+        devpath: dev.devpath_bytes().to_vec(),
+        sysname: dev.sysname_bytes().to_vec(),
+        subsystem: dev.subsystem_bytes().map(|s| s.to_vec()),
+        devtype: dev.devtype_bytes().map(|s| s.to_vec()),
+        driver: dev.driver_bytes().map(|s| s.to_vec()),
+        devnode: dev.devnode(),
+        devnum: dev.devnum(),
+        is_initialized: dev.is_initialized(),
+        properties: dev.iter_properties_bytes().map(|(key, value)| {
+            (key.to_vec(), value.map(|value| value.to_vec()))
+        }).collect(),
+        // Not every attribute is readable (some are write-only); skip those that aren't.
+        attributes: if with_attributes {
+            dev.iter_attributes_bytes().filter_map(|name| {
+                str::from_utf8(name).and_then(|name| dev.attribute_bytes(name).ok()).map(|value| {
+                    (name.to_vec(), value.to_vec())
+                })
+            }).collect()
+        } else {
+            Vec::new()
+        },
+        tags: dev.iter_tags_bytes().map(|tag| tag.to_vec()).collect(),
+        devlinks: dev.iter_devlinks().collect(),
+    }
+}
+
+// Build a snapshot from a device's properties (see `DeviceSnapshot::from_properties`) with the
+// syspath under `sys_root` and relative device nodes under `dev_root`.
+pub fn from_parts(sys_root: &Path, dev_root: &Path,
+                  properties: Vec<(Vec<u8>, Vec<u8>)>,
+                  attributes: Vec<(Vec<u8>, Vec<u8>)>) -> Option<DeviceSnapshot> {
+    let snapshot = {
+        let props = properties.as_slice();
+        let devpath = match lookup(props, "DEVPATH") {
+            Some(devpath) if !devpath.is_empty() => devpath.to_vec(),
+            _ => return None
+        };
+
+        let mut syspath = sys_root.clone();
+        syspath.push(devpath.slice_from(1));
+        let sysname = syspath.filename().unwrap_or(b"").to_vec();
+        let devnode = lookup_owned(props, "DEVNAME").map(|name| {
+            if name.as_slice().starts_with(b"/") {
+                Path::new(name)
+            } else {
+                dev_root.join(name)
+            }
+        });
+        let devnum = match (lookup_u32(props, "MAJOR"), lookup_u32(props, "MINOR")) {
+            (Some(major), Some(minor)) if major != 0 || minor != 0 => Some(Devnum::new(major, minor)),
+            _ => None
+        };
+
+        DeviceSnapshot {
+            syspath: syspath,
+            devpath: devpath,
+            sysname: sysname,
+            subsystem: lookup_owned(props, "SUBSYSTEM"),
+            devtype: lookup_owned(props, "DEVTYPE"),
+            driver: lookup_owned(props, "DRIVER"),
+            devnode: devnode,
+            devnum: devnum,
+            is_initialized: lookup(props, "USEC_INITIALIZED").is_some(),
+            tags: lookup(props, "TAGS").map_or(Vec::new(), |tags| words(tags, b':')),
+            devlinks: lookup(props, "DEVLINKS").map_or(Vec::new(), |links| {
+                words(links, b' ').into_iter().map(|link| Path::new(link)).collect()
+            }),
+            attributes: Vec::new(),
+            properties: Vec::new(),
+        }
+    };
+    Some(DeviceSnapshot {
+        properties: properties.into_iter().map(|(key, value)| (key, Some(value))).collect(),
+        attributes: attributes,
+        ..snapshot
+    })
+}
+
+pub fn lookup<'a>(list: &'a [(Vec<u8>, Vec<u8>)], name: &str) -> Option<&'a [u8]> {
+    list.iter()
+        .find(|&&(ref key, _)| key.as_slice() == name.as_bytes())
+        .map(|&(_, ref value)| value.as_slice())
+}
+
+fn lookup_owned(list: &[(Vec<u8>, Vec<u8>)], name: &str) -> Option<Vec<u8>> {
+    lookup(list, name).map(|value| value.to_vec())
+}
+
+fn lookup_u32(list: &[(Vec<u8>, Vec<u8>)], name: &str) -> Option<u32> {
+    lookup(list, name).and_then(str::from_utf8).and_then(|value| from_str(value.trim()))
+}
+
+fn words(value: &[u8], sep: u8) -> Vec<Vec<u8>> {
+    value.split(|&b| b == sep).filter(|w| !w.is_empty()).map(|w| w.to_vec()).collect()
+}
+
+impl DeviceSnapshot {
+    /// Create a snapshot from a device's udev properties.
+    ///
+    /// This is how libudev builds the devices it receives from a monitor: everything is derived
+    /// from the uevent properties (`DEVPATH`, `SUBSYSTEM`, `DEVTYPE`, `DRIVER`, `DEVNAME`,
+    /// `MAJOR`/`MINOR`, `DEVLINKS`, `TAGS`, etc.). The snapshot won't have any sysfs attributes.
+    ///
+    /// Returns `None` if the `DEVPATH` property is missing.
+    pub fn from_properties(properties: Vec<(Vec<u8>, Vec<u8>)>) -> Option<DeviceSnapshot> {
+        from_parts(&Path::new("/sys"), &Path::new("/dev"), properties, Vec::new())
+    }
+
+    /// Look the device up again in the specified udev context.
+    ///
+    /// Returns `None` if the device no longer exists.
+    pub fn device(&self, udev: &Udev) -> Option<Device> {
+        udev.device(&self.syspath)
+    }
+
+    /// Get the full path to the device (including `/sys`).
+    pub fn syspath(&self) -> &Path {
+        &self.syspath
+    }
+
+    /// Get the path to the device (minus `/sys`).
+    ///
+    /// Returns `None` if the path isn't valid UTF-8.
+    pub fn devpath(&self) -> Option<&str> {
+        str::from_utf8(self.devpath.as_slice())
+    }
+
+    /// Get the path to the device (minus `/sys`) as raw bytes.
+    pub fn devpath_bytes(&self) -> &[u8] {
+        self.devpath.as_slice()
+    }
+
+    /// Get the device name.
+    ///
+    /// Returns `None` if the name isn't valid UTF-8.
+    pub fn sysname(&self) -> Option<&str> {
+        str::from_utf8(self.sysname.as_slice())
+    }
+
+    /// Get the device name as raw bytes.
+    pub fn sysname_bytes(&self) -> &[u8] {
+        self.sysname.as_slice()
+    }
+
+    /// Get the device's subsystem.
+    pub fn subsystem(&self) -> Option<&str> {
+        self.subsystem_bytes().and_then(str::from_utf8)
+    }
+
+    /// Get the device's subsystem as raw bytes.
+    pub fn subsystem_bytes(&self) -> Option<&[u8]> {
+        self.subsystem.as_ref().map(|s| s.as_slice())
+    }
+
+    /// Get the device's devtype.
+    pub fn devtype(&self) -> Option<&str> {
+        self.devtype_bytes().and_then(str::from_utf8)
+    }
+
+    /// Get the device's devtype as raw bytes.
+    pub fn devtype_bytes(&self) -> Option<&[u8]> {
+        self.devtype.as_ref().map(|s| s.as_slice())
+    }
+
+    /// Get the device's driver.
+    pub fn driver(&self) -> Option<&str> {
+        self.driver_bytes().and_then(str::from_utf8)
+    }
+
+    /// Get the device's driver as raw bytes.
+    pub fn driver_bytes(&self) -> Option<&[u8]> {
+        self.driver.as_ref().map(|s| s.as_slice())
+    }
+
+    /// Get the device's devnode.
+    pub fn devnode(&self) -> Option<&Path> {
+        self.devnode.as_ref()
+    }
+
+    /// Get the device's devnum.
+    pub fn devnum(&self) -> Option<Devnum> {
+        self.devnum
+    }
+
+    /// Determine if the device had been initialized when the snapshot was taken.
+    pub fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+
+    /// Look up a property.
+    ///
+    /// Returns `None` if the property doesn't exist or isn't valid UTF-8.
+    pub fn property_value(&self, name: &str) -> Option<&str> {
+        self.property_value_bytes(name).and_then(str::from_utf8)
+    }
+
+    /// Look up a property as raw bytes.
+    ///
+    /// Returns `None` if the property doesn't exist or has no value.
+    pub fn property_value_bytes(&self, name: &str) -> Option<&[u8]> {
+        self.properties.iter()
+            .find(|&&(ref key, _)| key.as_slice() == name.as_bytes())
+            .and_then(|&(_, ref value)| value.as_ref().map(|value| value.as_slice()))
+    }
+
+    /// Get all properties as (name, value) pairs.
+    ///
+    /// Like `Device::iter_properties_bytes`, properties without a value have a value of `None`.
+    pub fn properties(&self) -> &[(Vec<u8>, Option<Vec<u8>>)] {
+        self.properties.as_slice()
+    }
+
+    /// Look up the value a sysfs attribute had when the snapshot was taken.
+    ///
+    /// Returns `None` if the attribute wasn't readable or isn't valid UTF-8.
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attribute_bytes(name).and_then(str::from_utf8)
+    }
+
+    /// Look up the value a sysfs attribute had when the snapshot was taken as raw bytes.
+    pub fn attribute_bytes(&self, name: &str) -> Option<&[u8]> {
+        lookup(self.attributes.as_slice(), name)
+    }
+
+    /// Get all readable sysfs attributes as (name, value) pairs.
+    ///
+    /// Empty unless the snapshot was taken with its attributes.
+    pub fn attributes(&self) -> &[(Vec<u8>, Vec<u8>)] {
+        self.attributes.as_slice()
+    }
+
+    /// Check whether the device was tagged with a given tag.
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t.as_slice() == tag.as_bytes())
+    }
+
+    /// Get the device's tags.
+    pub fn tags(&self) -> &[Vec<u8>] {
+        self.tags.as_slice()
+    }
+
+    /// Get the device's devlinks.
+    pub fn devlinks(&self) -> &[Path] {
+        self.devlinks.as_slice()
+    }
+}
+
+impl fmt::Show for DeviceSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.syspath.display())
+    }
+}
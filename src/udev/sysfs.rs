@@ -0,0 +1,752 @@
+// This file is part of udev-rs.
+// 
+// Copyright 2014 Steven Allen <steven@stebalien.com>
+// 
+// udev-rs is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation; either version 2.1 of the License, or
+// (at your option) any later version.
+// 
+// udev-rs is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+// 
+// You should have received a copy of the GNU Lesser General Public License
+// along with udev-rs; If not, see <http://www.gnu.org/licenses/>.
+use std::{mem, os, str};
+use std::cell::RefCell;
+use std::io::{File, TypeFile, TypeSymlink, TypeDirectory};
+use std::io::fs;
+use std::rc::Rc;
+use std::time::Duration;
+
+use libc::{EINVAL, EISDIR, ENODEV, ENOENT, ELOOP};
+
+use udev::{
+    db,
+    error,
+    filter,
+    snapshot,
+    util,
+};
+use udev::devnum::{Devnum, Type};
+use udev::error::Error;
+use udev::filter::{Filter, Matchable};
+use udev::snapshot::DeviceSnapshot;
+
+/// Where the pure-Rust backend finds devices: the sysfs root, the device node directory and
+/// udev's runtime directory (the one containing `data/`).
+///
+/// This is the state of a sysfs-backed `Udev` context (see `UdevBuilder`). Cloning is cheap and
+/// every device read through it keeps a handle so it can look up its parents under the same
+/// roots.
+#[deriving(Clone)]
+pub struct Sysfs {
+    roots: Rc<Roots>,
+}
+
+struct Roots {
+    sys_root: Path,
+    dev_root: Path,
+    run_dir: Path,
+}
+
+/// A device read from sysfs (or built from an event's properties).
+///
+/// Properties, tags and devlinks are read when the device is created; only the names of its
+/// attributes are listed. Attribute values are read the first time they're asked for and then
+/// cached, like libudev does.
+pub struct SysfsDevice {
+    sysfs: Sysfs,
+    snapshot: DeviceSnapshot,
+    attribute_names: Vec<Vec<u8>>,
+    // The cached attributes: each name with the index of its current value in `values`.
+    attributes: RefCell<Vec<(Vec<u8>, uint)>>,
+    // Every distinct attribute value read or written so far. This only ever grows and boxed
+    // buffers don't move when it does, so slices into them stay valid as long as the device
+    // (even after the attribute is set to something else).
+    values: RefCell<Vec<Box<[u8]>>>,
+}
+
+/// The state of a sysfs-backed `Enumerator`.
+pub struct Scan {
+    pub sysfs: Sysfs,
+    pub filter: Filter,
+    pub parent: Option<Path>,
+    pub initialized: bool,
+    pub syspaths: Vec<Path>,
+}
+
+impl Sysfs {
+    /// Read devices from `/sys`, `/dev` and `/run/udev`.
+    pub fn new() -> Sysfs {
+        Sysfs::with_roots(Path::new("/sys"), Path::new("/dev"), Path::new("/run/udev"))
+    }
+
+    /// Read devices from the specified sysfs root, device node directory, and udev runtime
+    /// directory.
+    pub fn with_roots(sys_root: Path, dev_root: Path, run_dir: Path) -> Sysfs {
+        Sysfs {
+            roots: Rc::new(Roots {
+                sys_root: sys_root,
+                dev_root: dev_root,
+                run_dir: run_dir,
+            })
+        }
+    }
+
+    /// Get the sysfs root.
+    pub fn sys_root(&self) -> &Path {
+        &self.roots.sys_root
+    }
+
+    /// Get the device node directory.
+    pub fn dev_root(&self) -> &Path {
+        &self.roots.dev_root
+    }
+
+    /// Get the udev runtime directory.
+    pub fn run_dir(&self) -> &Path {
+        &self.roots.run_dir
+    }
+
+    /// Read the device at a sys path.
+    ///
+    /// The path may be a symlink to the device (e.g. under `/sys/class`). Besides devices, this
+    /// reads the subsystems, drivers and modules listed by `Enumerator::scan_subsystems`.
+    pub fn device(&self, path: &Path) -> Result<SysfsDevice, Error> {
+        let syspath = try!(resolve(path));
+        let sys_root = self.sys_root();
+        if !sys_root.is_ancestor_of(&syspath) || *sys_root == syspath {
+            return Err(error::os(ENODEV, "device", Some(path.display().to_string().as_slice())));
+        }
+        let relative = syspath.path_relative_from(sys_root).unwrap();
+        let mut devpath = b"/".to_vec();
+        devpath.push_all(relative.as_vec());
+        let mut properties = vec![(b"DEVPATH".to_vec(), devpath)];
+
+        let components: Vec<&[u8]> = relative.components().collect();
+        if components[0] == b"devices" && components.len() > 1 {
+            if let Some(subsystem) = link_name(&syspath.join("subsystem")) {
+                properties.push((b"SUBSYSTEM".to_vec(), subsystem));
+            }
+            let uevent = try!(read_file(&syspath.join("uevent")));
+            for line in uevent.as_slice().split(|&b| b == b'\n') {
+                if let Some(i) = line.iter().position(|&b| b == b'=') {
+                    set(&mut properties, line.slice_to(i), line.slice_from(i + 1));
+                }
+            }
+            if !properties.iter().any(|&(ref key, _)| key.as_slice() == b"DRIVER") {
+                if let Some(driver) = link_name(&syspath.join("driver")) {
+                    properties.push((b"DRIVER".to_vec(), driver));
+                }
+            }
+            if let Some(id) = device_id(properties.as_slice(), syspath.filename().unwrap_or(b"")) {
+                if let Ok(record) = db::Record::read(&self.run_dir().join("data").join(id)) {
+                    self.merge_db(&mut properties, &record);
+                }
+            }
+        } else {
+            // libudev's names for these pseudo-devices' subsystems.
+            let subsystem = if components.len() == 2 && components[0] == b"bus" {
+                "subsystem"
+            } else if components.len() == 4 && components[0] == b"bus" && components[2] == b"drivers" {
+                "drivers"
+            } else if components.len() == 2 && components[0] == b"module" {
+                "module"
+            } else {
+                return Err(error::os(ENODEV, "device", Some(path.display().to_string().as_slice())));
+            };
+            properties.push((b"SUBSYSTEM".to_vec(), subsystem.as_bytes().to_vec()));
+        }
+
+        match self.from_properties(properties) {
+            Some(dev) => Ok(dev),
+            None => Err(error::unknown("device", Some(path.display().to_string().as_slice())))
+        }
+    }
+
+    /// Build a device from its udev properties (e.g. those of a received event).
+    ///
+    /// Returns `None` if the `DEVPATH` property is missing.
+    pub fn from_properties(&self, properties: Vec<(Vec<u8>, Vec<u8>)>) -> Option<SysfsDevice> {
+        snapshot::from_parts(self.sys_root(), self.dev_root(), properties, Vec::new()).map(|snapshot| {
+            SysfsDevice {
+                sysfs: self.clone(),
+                attribute_names: attribute_names(snapshot.syspath()),
+                snapshot: snapshot,
+                attributes: RefCell::new(Vec::new()),
+                values: RefCell::new(Vec::new()),
+            }
+        })
+    }
+
+    /// Build a device from the environment udev passes to the programs it runs.
+    pub fn from_environment(&self) -> Result<SysfsDevice, Error> {
+        let properties = os::env_as_bytes();
+        if snapshot::lookup(properties.as_slice(), "SUBSYSTEM").is_none() {
+            return Err(error::os(EINVAL, "device_from_environment", None));
+        }
+        match self.from_properties(properties) {
+            Some(dev) => Ok(dev),
+            None => Err(error::os(EINVAL, "device_from_environment", None))
+        }
+    }
+
+    /// Lookup a device by device type and device number.
+    pub fn device_from_devnum(&self, ty: Type, devnum: Devnum) -> Option<SysfsDevice> {
+        let kind = match ty {
+            Type::Block => "block",
+            Type::Char => "char",
+        };
+        self.device(&self.sys_root().join("dev").join(kind).join(devnum.to_string())).ok()
+    }
+
+    /// Lookup a device by subsystem and sysname
+    pub fn device_from_subsystem_sysname(&self, subsystem: &str, sysname: &str) -> Option<SysfsDevice> {
+        // Slashes in sysnames are replaced by `!` in sysfs.
+        let sysname = sysname.replace("/", "!");
+        let sys_root = self.sys_root();
+        let candidates = match subsystem {
+            "subsystem" => vec![sys_root.join("bus").join(sysname.as_slice())],
+            "module" => vec![sys_root.join("module").join(sysname.as_slice())],
+            _ => vec![
+                sys_root.join("bus").join(subsystem).join("devices").join(sysname.as_slice()),
+                sys_root.join("class").join(subsystem).join(sysname.as_slice()),
+            ]
+        };
+        candidates.iter().filter_map(|path| self.device(path).ok()).next()
+    }
+
+    /// Lookup a device by device id (see `Udev::device_from_device_id`).
+    pub fn device_from_device_id(&self, id: &str) -> Result<SysfsDevice, Error> {
+        if id.is_empty() {
+            return Err(error::os(EINVAL, "device_from_device_id", Some(id)));
+        }
+        let rest = id.slice_from(1);
+        let dev = match id.char_at(0) {
+            'b' | 'c' => {
+                let ty = if id.starts_with("b") { Type::Block } else { Type::Char };
+                match from_str::<Devnum>(rest) {
+                    Some(devnum) => self.device_from_devnum(ty, devnum),
+                    None => return Err(error::os(EINVAL, "device_from_device_id", Some(id)))
+                }
+            },
+            'n' if from_str::<u32>(rest).is_some() => {
+                let mut scan = Scan::new(self.clone());
+                scan.filter = Filter::new().match_subsystem("net").match_property("IFINDEX", Some(rest));
+                try!(scan.scan_devices());
+                scan.syspaths.iter().filter_map(|path| self.device(path).ok()).next()
+            },
+            '+' if rest.contains_char(':') => {
+                let i = rest.find(':').unwrap();
+                self.device_from_subsystem_sysname(rest.slice_to(i), rest.slice_from(i + 1))
+            },
+            _ => return Err(error::os(EINVAL, "device_from_device_id", Some(id)))
+        };
+        match dev {
+            Some(dev) => Ok(dev),
+            None => Err(error::os(ENODEV, "device_from_device_id", Some(id)))
+        }
+    }
+
+    // Merge a udev database record into the device's properties.
+    fn merge_db(&self, properties: &mut Vec<(Vec<u8>, Vec<u8>)>, record: &db::Record) {
+        for &(ref name, ref value) in record.properties.iter() {
+            set(properties, name.as_slice(), value.as_slice());
+        }
+        if let Some(usec) = record.usec_initialized {
+            set(properties, b"USEC_INITIALIZED", usec.to_string().as_bytes());
+        }
+        if !record.devlinks.is_empty() {
+            let devlinks: Vec<Vec<u8>> = record.devlinks.iter().map(|link| {
+                self.dev_root().join(link.as_slice()).into_vec()
+            }).collect();
+            set(properties, b"DEVLINKS", devlinks.connect(&b' ').as_slice());
+        }
+        if !record.tags.is_empty() {
+            let mut value = b":".to_vec();
+            for tag in record.tags.iter() {
+                value.push_all(tag.as_slice());
+                value.push(b':');
+            }
+            set(properties, b"TAGS", value.as_slice());
+        }
+    }
+}
+
+impl SysfsDevice {
+    /// Get the device's properties, tags, devlinks, etc.
+    ///
+    /// The snapshot has no attributes (see `attribute_bytes`).
+    pub fn snapshot(&self) -> &DeviceSnapshot {
+        &self.snapshot
+    }
+
+    /// Get the names of the device's attributes (the regular files in its sysfs directory).
+    pub fn attribute_names(&self) -> &[Vec<u8>] {
+        self.attribute_names.as_slice()
+    }
+
+    /// Get the device's parent: the closest ancestor directory that's a device.
+    pub fn parent(&self) -> Option<SysfsDevice> {
+        let devices = self.sysfs.sys_root().join("devices");
+        let mut path = self.snapshot.syspath().dir_path();
+        while devices.is_ancestor_of(&path) && path != devices {
+            if let Ok(dev) = self.sysfs.device(&path) {
+                return Some(dev);
+            }
+            path = path.dir_path();
+        }
+        None
+    }
+
+    /// Get the number at the end of the device's name (e.g. the 0 in `tty0`).
+    pub fn sysnum(&self) -> Option<u64> {
+        let sysname = self.snapshot.sysname_bytes();
+        let start = sysname.iter().rposition(|&b| b < b'0' || b > b'9').map_or(0, |i| i + 1);
+        if start == sysname.len() {
+            None
+        } else {
+            str::from_utf8(sysname.slice_from(start)).and_then(from_str)
+        }
+    }
+
+    /// Get the time since udev initialized the device.
+    pub fn time_since_initialized(&self) -> Option<Duration> {
+        // udev records the (monotonic) time of initialization in microseconds.
+        self.snapshot.property_value("USEC_INITIALIZED").and_then(from_str::<i64>).and_then(|usec| {
+            if usec == 0 {
+                None
+            } else {
+                Some(util::monotonic_now() - Duration::microseconds(usec))
+            }
+        })
+    }
+
+    /// Read an attribute (minus trailing whitespace), caching its value.
+    ///
+    /// Like libudev, the `driver`, `subsystem` and `module` links read as the name of the file
+    /// they point to.
+    pub fn attribute_bytes<'s>(&'s self, attr: &str) -> Result<&'s [u8], Error> {
+        let cached = self.attributes.borrow().iter()
+            .find(|&&(ref name, _)| name.as_slice() == attr.as_bytes())
+            .map(|&(_, index)| index);
+        if let Some(index) = cached {
+            return Ok(self.value(index));
+        }
+        let value = try!(self.read_attribute(attr));
+        Ok(self.cache(attr, value))
+    }
+
+    /// Write an attribute.
+    pub fn set_attribute(&self, attr: &str, value: &str) -> Result<(), Error> {
+        let path = self.snapshot.syspath().join(attr);
+        try!(util::write_file(&path, value.as_bytes()).map_err(|e| {
+            error::os(e, "write", Some(path.display().to_string().as_slice()))
+        }));
+        self.cache(attr, util::trim_end(value.as_bytes()).to_vec());
+        Ok(())
+    }
+
+    fn read_attribute(&self, attr: &str) -> Result<Vec<u8>, Error> {
+        let path = self.snapshot.syspath().join(attr);
+        let stat = try!(fs::lstat(&path).map_err(|e| util::io_error(&e, "lstat", &path)));
+        match stat.kind {
+            TypeSymlink if attr == "driver" || attr == "subsystem" || attr == "module" => {
+                match link_name(&path) {
+                    Some(name) => Ok(name),
+                    None => Err(error::os(ENOENT, "readlink", Some(path.display().to_string().as_slice())))
+                }
+            },
+            TypeDirectory => Err(error::os(EISDIR, "read", Some(path.display().to_string().as_slice()))),
+            TypeFile => read_file(&path).map(|value| util::trim_end(value.as_slice()).to_vec()),
+            _ => Err(error::os(EINVAL, "read", Some(path.display().to_string().as_slice())))
+        }
+    }
+
+    // Make `value` the attribute's cached value, replacing the previous one.
+    fn cache<'s>(&'s self, attr: &str, value: Vec<u8>) -> &'s [u8] {
+        let index = {
+            let mut values = self.values.borrow_mut();
+            match values.iter().position(|v| v.as_slice() == value.as_slice()) {
+                Some(index) => index,
+                None => {
+                    values.push(value.into_boxed_slice());
+                    values.len() - 1
+                }
+            }
+        };
+        {
+            let mut attributes = self.attributes.borrow_mut();
+            match attributes.iter().position(|&(ref name, _)| name.as_slice() == attr.as_bytes()) {
+                Some(i) => attributes.as_mut_slice()[i] = (attr.as_bytes().to_vec(), index),
+                None => attributes.push((attr.as_bytes().to_vec(), index)),
+            }
+        }
+        self.value(index)
+    }
+
+    fn value<'s>(&'s self, index: uint) -> &'s [u8] {
+        // `values` never drops or moves a buffer (see the field) so the slice lives as long as
+        // the device rather than the `RefCell` borrow.
+        let values = self.values.borrow();
+        unsafe { mem::transmute(values[index].as_slice()) }
+    }
+}
+
+impl Matchable for SysfsDevice {
+    fn syspath_bytes(&self) -> &[u8] {
+        self.snapshot.syspath().as_vec()
+    }
+
+    fn sysname_bytes(&self) -> &[u8] {
+        self.snapshot.sysname_bytes()
+    }
+
+    fn subsystem_bytes(&self) -> Option<&[u8]> {
+        self.snapshot.subsystem_bytes()
+    }
+
+    fn property_value_bytes(&self, name: &str) -> Option<&[u8]> {
+        self.snapshot.property_value_bytes(name)
+    }
+
+    fn attribute_value(&self, name: &str) -> Option<Vec<u8>> {
+        self.attribute_bytes(name).ok().map(|value| value.to_vec())
+    }
+
+    fn has_tag(&self, tag: &str) -> bool {
+        self.snapshot.has_tag(tag)
+    }
+
+    fn has_devlink(&self, link: &Path) -> bool {
+        self.snapshot.devlinks().iter().any(|l| l == link)
+    }
+}
+
+impl Scan {
+    /// Create an empty scan that matches every device.
+    pub fn new(sysfs: Sysfs) -> Scan {
+        Scan {
+            sysfs: sysfs,
+            filter: Filter::new(),
+            parent: None,
+            initialized: false,
+            syspaths: Vec::new(),
+        }
+    }
+
+    /// Add the devices matching the scan's filters.
+    ///
+    /// Like libudev, this finds devices through the `bus` and `class` directories so
+    /// restricting the filter to specific subsystems makes this much faster. Only the
+    /// attributes the filter matches on are read.
+    pub fn scan_devices(&mut self) -> Result<(), Error> {
+        let sys_root = self.sysfs.sys_root().clone();
+        if !sys_root.is_dir() {
+            return Err(error::os(ENOENT, "scan_devices", Some(sys_root.display().to_string().as_slice())));
+        }
+        let mut candidates = Vec::new();
+        {
+            let subsystems = filter::filter_subsystems(&self.filter);
+            for kind in ["bus", "class"].iter() {
+                let base = sys_root.join(*kind);
+                let dirs = if subsystems.is_empty() {
+                    fs::readdir(&base).unwrap_or(Vec::new())
+                } else {
+                    subsystems.iter().map(|s| base.join(s.as_slice())).collect()
+                };
+                for dir in dirs.into_iter() {
+                    let dir = if *kind == "bus" { dir.join("devices") } else { dir };
+                    candidates.extend(fs::readdir(&dir).unwrap_or(Vec::new()).into_iter());
+                }
+            }
+        }
+        self.add_matching(candidates);
+        Ok(())
+    }
+
+    /// Add the subsystems, drivers and modules matching the scan's filters.
+    pub fn scan_subsystems(&mut self) -> Result<(), Error> {
+        let sys_root = self.sysfs.sys_root().clone();
+        if !sys_root.is_dir() {
+            return Err(error::os(ENOENT, "scan_subsystems", Some(sys_root.display().to_string().as_slice())));
+        }
+        let mut candidates = fs::readdir(&sys_root.join("module")).unwrap_or(Vec::new());
+        for bus in fs::readdir(&sys_root.join("bus")).unwrap_or(Vec::new()).into_iter() {
+            candidates.extend(fs::readdir(&bus.join("drivers")).unwrap_or(Vec::new()).into_iter());
+            candidates.push(bus);
+        }
+        self.add_matching(candidates);
+        Ok(())
+    }
+
+    /// Add a device regardless of the filters.
+    pub fn add(&mut self, syspath: Path) {
+        self.syspaths.push(syspath);
+        self.sort();
+    }
+
+    fn add_matching(&mut self, candidates: Vec<Path>) {
+        let filter = match self.parent {
+            Some(ref parent) => self.filter.clone().match_parent(parent),
+            None => self.filter.clone()
+        };
+        for candidate in candidates.iter() {
+            if let Ok(dev) = self.sysfs.device(candidate) {
+                if (!self.initialized || dev.snapshot.is_initialized()) && filter.matches(&dev) {
+                    self.syspaths.push(dev.snapshot.syspath().clone());
+                }
+            }
+        }
+        self.sort();
+    }
+
+    fn sort(&mut self) {
+        self.syspaths.sort_by(|a, b| a.as_vec().cmp(b.as_vec()));
+        self.syspaths.dedup();
+    }
+}
+
+// Replace or add a property.
+fn set(properties: &mut Vec<(Vec<u8>, Vec<u8>)>, key: &[u8], value: &[u8]) {
+    match properties.iter_mut().find(|&&(ref k, _)| k.as_slice() == key) {
+        Some(&(_, ref mut v)) => {
+            *v = value.to_vec();
+            return;
+        },
+        None => ()
+    }
+    properties.push((key.to_vec(), value.to_vec()));
+}
+
+// The name of the device's udev database file (see `Udev::device_from_device_id`).
+fn device_id(properties: &[(Vec<u8>, Vec<u8>)], sysname: &[u8]) -> Option<Vec<u8>> {
+    let subsystem = snapshot::lookup(properties, "SUBSYSTEM");
+    match (snapshot::lookup(properties, "MAJOR"),
+           snapshot::lookup(properties, "MINOR"),
+           snapshot::lookup(properties, "IFINDEX")) {
+        (Some(major), Some(minor), _) if major != b"0" || minor != b"0" => {
+            let mut id = if subsystem == Some(b"block") { b"b".to_vec() } else { b"c".to_vec() };
+            id.push_all(major);
+            id.push(b':');
+            id.push_all(minor);
+            Some(id)
+        },
+        (_, _, Some(ifindex)) if ifindex != b"0" => {
+            let mut id = b"n".to_vec();
+            id.push_all(ifindex);
+            Some(id)
+        },
+        _ => subsystem.map(|subsystem| {
+            let mut id = b"+".to_vec();
+            id.push_all(subsystem);
+            id.push(b':');
+            id.push_all(sysname);
+            id
+        })
+    }
+}
+
+// List the regular files in a device's directory (without reading them).
+fn attribute_names(syspath: &Path) -> Vec<Vec<u8>> {
+    let mut names: Vec<Vec<u8>> = fs::readdir(syspath).unwrap_or(Vec::new()).iter().filter(|entry| {
+        match fs::lstat(*entry) {
+            Ok(ref stat) => stat.kind == TypeFile,
+            Err(_) => false
+        }
+    }).filter_map(|entry| entry.filename().map(|name| name.to_vec())).collect();
+    names.sort();
+    names
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, Error> {
+    File::open(path).read_to_end().map_err(|e| util::io_error(&e, "read", path))
+}
+
+// Get the name of the file a symlink points to.
+fn link_name(path: &Path) -> Option<Vec<u8>> {
+    fs::readlink(path).ok().and_then(|target| target.filename().map(|name| name.to_vec()))
+}
+
+// Follow symlinks in the last path component (sysfs only links to directories).
+fn resolve(path: &Path) -> Result<Path, Error> {
+    let mut path = path.clone();
+    // Same limit as the kernel.
+    for _ in range(0u, 40) {
+        match fs::lstat(&path) {
+            Ok(ref stat) if stat.kind == TypeSymlink => {
+                let target = try!(fs::readlink(&path).map_err(|e| util::io_error(&e, "readlink", &path)));
+                path = path.dir_path().join(target);
+            },
+            Ok(_) => return Ok(path),
+            Err(e) => return Err(util::io_error(&e, "lstat", &path))
+        }
+    }
+    Err(error::os(ELOOP, "readlink", Some(path.display().to_string().as_slice())))
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{File, TempDir, USER_RWX};
+    use std::io::fs;
+
+    use udev::devnum::{Devnum, Type};
+    use udev::builder::UdevBuilder;
+    use udev::udev::Udev;
+    use super::Sysfs;
+
+    fn file(path: &Path, contents: &str) {
+        fs::mkdir_recursive(&path.dir_path(), USER_RWX).unwrap();
+        File::create(path).write(contents.as_bytes()).unwrap();
+    }
+
+    fn link(path: &Path, target: &str) {
+        fs::mkdir_recursive(&path.dir_path(), USER_RWX).unwrap();
+        fs::symlink(&Path::new(target), path).unwrap();
+    }
+
+    // A loop device (with a udev database entry) and a tty.
+    fn fixture() -> (TempDir, Udev) {
+        let dir = TempDir::new("udev-rs-sysfs").unwrap();
+        let (sys, run) = (dir.path().join("sys"), dir.path().join("run"));
+
+        let loop0 = sys.join("devices/virtual/block/loop0");
+        file(&loop0.join("uevent"), "MAJOR=7\nMINOR=0\nDEVNAME=loop0\nDEVTYPE=disk\n");
+        file(&loop0.join("size"), "2048\n");
+        link(&loop0.join("subsystem"), "../../../../class/block");
+        link(&sys.join("class/block/loop0"), "../../devices/virtual/block/loop0");
+        link(&sys.join("dev/block/7:0"), "../../devices/virtual/block/loop0");
+        file(&run.join("data/b7:0"), "S:disk/by-uuid/1234\nI:5000\nE:ID_FS_TYPE=ext4\nG:systemd\n");
+
+        let tty0 = sys.join("devices/virtual/tty/tty0");
+        file(&tty0.join("uevent"), "MAJOR=4\nMINOR=0\nDEVNAME=tty0\n");
+        link(&tty0.join("subsystem"), "../../../../class/tty");
+        link(&sys.join("class/tty/tty0"), "../../devices/virtual/tty/tty0");
+
+        let udev = UdevBuilder::new()
+            .sysfs_root(sys)
+            .dev_root(dir.path().join("dev"))
+            .udev_run_dir(run)
+            .build()
+            .unwrap();
+        (dir, udev)
+    }
+
+    #[test]
+    fn test_device() {
+        let (dir, udev) = fixture();
+        let dev = udev.device(&dir.path().join("sys/class/block/loop0")).unwrap();
+        assert_eq!(dev.syspath(), dir.path().join("sys/devices/virtual/block/loop0"));
+        assert_eq!(dev.devpath(), Some("/devices/virtual/block/loop0"));
+        assert_eq!(dev.sysname(), Some("loop0"));
+        assert_eq!(dev.subsystem(), Some("block"));
+        assert_eq!(dev.devtype(), Some("disk"));
+        assert_eq!(dev.devnum(), Some(Devnum::new(7, 0)));
+        assert_eq!(dev.devnode(), Some(dir.path().join("dev/loop0")));
+        assert_eq!(dev.property_value("ID_FS_TYPE"), Some("ext4"));
+        assert_eq!(dev.iter_devlinks().collect::<Vec<Path>>(), vec![dir.path().join("dev/disk/by-uuid/1234")]);
+        assert!(dev.has_tag("systemd"));
+        assert!(dev.is_initialized());
+        assert_eq!(dev.sysnum(), Some(0));
+        assert!(dev.parent().is_none());
+
+        // `{}` prints the syspath; `info` prints everything else.
+        assert_eq!(format!("{}", dev), dir.path().join("sys/devices/virtual/block/loop0").display().to_string());
+        assert!(format!("{}", dev.info()).as_slice().starts_with("P: /devices/virtual/block/loop0\n"));
+
+        let tty = udev.device(&dir.path().join("sys/devices/virtual/tty/tty0")).unwrap();
+        assert!(!tty.is_initialized());
+        assert!(tty.time_since_initialized().is_none());
+        assert!(udev.device(&dir.path().join("sys/devices/virtual/tty/tty9")).is_none());
+    }
+
+    #[test]
+    fn test_lazy_attributes() {
+        let (dir, udev) = fixture();
+        let size = dir.path().join("sys/devices/virtual/block/loop0/size");
+        let dev = udev.device(&size.dir_path()).unwrap();
+        assert_eq!(dev.iter_attributes().collect::<Vec<&str>>(), vec!["size", "uevent"]);
+
+        // Values are read when first asked for and cached from then on.
+        file(&size, "4096\n");
+        assert_eq!(dev.attribute("size").unwrap(), "4096");
+        file(&size, "8192\n");
+        assert_eq!(dev.attribute("size").unwrap(), "4096");
+
+        dev.set_attribute("size", "1024").unwrap();
+        assert_eq!(dev.attribute("size").unwrap(), "1024");
+        assert_eq!(File::open(&size).read_to_string().unwrap().as_slice(), "1024");
+        assert_eq!(dev.attribute("subsystem").unwrap(), "block");
+        assert!(dev.attribute("missing").is_err());
+    }
+
+    #[test]
+    fn test_attribute_cache() {
+        let (dir, _udev) = fixture();
+        let sysfs = Sysfs::with_roots(dir.path().join("sys"), dir.path().join("dev"), dir.path().join("run"));
+        let dev = sysfs.device(&dir.path().join("sys/devices/virtual/block/loop0")).unwrap();
+
+        // Values handed out earlier outlive later writes.
+        let before = dev.attribute_bytes("size").unwrap();
+        dev.set_attribute("size", "1024").unwrap();
+        assert_eq!(before, b"2048");
+        assert_eq!(dev.attribute_bytes("size").unwrap(), b"1024");
+
+        // Writing replaces the cached value; repeated values are only stored once.
+        for i in range(0u, 10) {
+            dev.set_attribute("size", if i % 2 == 0 { "4096" } else { "1024" }).unwrap();
+        }
+        assert_eq!(dev.attribute_bytes("size").unwrap(), b"1024");
+        assert_eq!(dev.attributes.borrow().len(), 1);
+        assert_eq!(dev.values.borrow().len(), 3);
+    }
+
+    #[test]
+    fn test_lookups() {
+        let (_dir, udev) = fixture();
+        let by_devnum = udev.device_from_devnum(Type::Block, Devnum::new(7, 0)).unwrap();
+        assert_eq!(by_devnum.sysname(), Some("loop0"));
+        let tty0 = udev.device_from_subsystem_sysname("tty", "tty0").unwrap();
+        assert_eq!(tty0.sysname(), Some("tty0"));
+        assert_eq!(udev.device_from_device_id("b7:0").unwrap(), by_devnum);
+        assert_eq!(udev.device_from_device_id("+tty:tty0").unwrap().subsystem(), Some("tty"));
+        assert!(udev.device_from_device_id("c7:0").is_err());
+        assert!(udev.device_from_device_id("x").is_err());
+    }
+
+    #[test]
+    fn test_enumerator() {
+        // Scan for devices and return their sysnames.
+        fn scan(udev: &Udev, subsystem: Option<&str>, property: Option<(&str, &str)>,
+                attribute: Option<(&str, &str)>, initialized: bool) -> Vec<String> {
+            let mut e = udev.enumerator().unwrap();
+            if let Some(subsystem) = subsystem {
+                e = e.match_subsystem(subsystem).unwrap();
+            }
+            if let Some((name, value)) = property {
+                e = e.match_property(name, Some(value)).unwrap();
+            }
+            if let Some((name, value)) = attribute {
+                e = e.match_attribute(name, Some(value)).unwrap();
+            }
+            if initialized {
+                e = e.match_is_initialized().unwrap();
+            }
+            let e = e.scan_devices().unwrap();
+            e.iter().map(|dev| dev.sysname_lossy()).collect()
+        }
+
+        let (dir, udev) = fixture();
+        assert_eq!(scan(&udev, None, None, None, false), vec!["loop0".to_string(), "tty0".to_string()]);
+        assert_eq!(scan(&udev, Some("tty"), None, None, false), vec!["tty0".to_string()]);
+        assert_eq!(scan(&udev, None, Some(("ID_FS_TYPE", "ext*")), None, false), vec!["loop0".to_string()]);
+        assert_eq!(scan(&udev, None, None, Some(("size", "2048")), false), vec!["loop0".to_string()]);
+        assert_eq!(scan(&udev, None, None, None, true), vec!["loop0".to_string()]);
+
+        let missing = UdevBuilder::new().sysfs_root(dir.path().join("nothing")).build().unwrap();
+        assert!(missing.enumerator().and_then(|e| e.scan_devices()).is_err());
+    }
+}
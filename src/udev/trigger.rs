@@ -0,0 +1,276 @@
+// This file is part of udev-rs.
+// 
+// Copyright 2014 Steven Allen <steven@stebalien.com>
+// 
+// udev-rs is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation; either version 2.1 of the License, or
+// (at your option) any later version.
+// 
+// udev-rs is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+// 
+// You should have received a copy of the GNU Lesser General Public License
+// along with udev-rs; If not, see <http://www.gnu.org/licenses/>.
+
+use std::rand;
+use std::time::Duration;
+use std::collections::HashSet;
+
+use libc::{ENOENT, ENODEV};
+
+use udev::{
+    error,
+    util,
+};
+use udev::monitor;
+use udev::udev::Udev;
+use udev::error::Error;
+use udev::event::Action;
+
+/// A synthetic uevent (like `udevadm trigger`).
+///
+/// Triggering a device writes to its `uevent` file in sysfs; the kernel then sends an event for
+/// the device as if it had happened, which udev processes as usual. The event can carry a
+/// `SYNTH_UUID` (to recognize it later, see `Event::synth_uuid`) and extra `SYNTH_ARG_*`
+/// properties.
+///
+/// ```ignore
+/// let trigger = Trigger::new(Action::Change).wait(Duration::seconds(5));
+/// try!(dev.trigger_with(&trigger));
+/// ```
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub struct Trigger {
+    action: Action,
+    uuid: Option<String>,
+    args: Vec<(String, String)>,
+    wait: Option<Duration>,
+}
+
+impl Trigger {
+    /// Create a trigger for the specified action.
+    pub fn new(action: Action) -> Trigger {
+        Trigger {
+            action: action,
+            uuid: None,
+            args: Vec::new(),
+            wait: None,
+        }
+    }
+
+    /// Tag the event with the specified UUID (sent as `SYNTH_UUID`).
+    pub fn uuid(mut self, uuid: &str) -> Trigger {
+        self.uuid = Some(uuid.to_string());
+        self
+    }
+
+    /// Add an extra property (sent as `SYNTH_ARG_<key>`).
+    ///
+    /// The kernel only accepts arguments after a UUID so one is generated if none has been set.
+    pub fn arg(mut self, key: &str, value: &str) -> Trigger {
+        self.ensure_uuid();
+        self.args.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Wait (at most `timeout`) for udev to finish processing the triggered events.
+    ///
+    /// The events are recognized by their UUID so one is generated if none has been set. This
+    /// replaces `udevadm trigger --settle`.
+    pub fn wait(mut self, timeout: Duration) -> Trigger {
+        self.ensure_uuid();
+        self.wait = Some(timeout);
+        self
+    }
+
+    /// Get the event's action.
+    pub fn action(&self) -> &Action {
+        &self.action
+    }
+
+    /// Get the event's UUID (if any).
+    pub fn get_uuid(&self) -> Option<&str> {
+        self.uuid.as_ref().map(|u| u.as_slice())
+    }
+
+    /// Write this trigger to the `uevent` file of the device at `syspath`.
+    ///
+    /// This doesn't wait, even if `wait` was specified. The syspath doesn't have to be under
+    /// `/sys` (e.g. for testing against a fixture tree).
+    pub fn write(&self, syspath: &Path) -> Result<(), Error> {
+        let path = syspath.join("uevent");
+        util::write_file(&path, self.message().as_bytes()).map_err(|e| {
+            error::os(e, "write", Some(path.display().to_string().as_slice()))
+        })
+    }
+
+    // Format: `ACTION [UUID [KEY=VALUE ...]]`
+    fn message(&self) -> String {
+        let mut message = self.action.as_str().to_string();
+        if let Some(ref uuid) = self.uuid {
+            message.push(' ');
+            message.push_str(uuid.as_slice());
+            for &(ref key, ref value) in self.args.iter() {
+                message.push_str(format!(" {}={}", key, value).as_slice());
+            }
+        }
+        message
+    }
+
+    fn ensure_uuid(&mut self) {
+        if self.uuid.is_none() {
+            self.uuid = Some(random_uuid());
+        }
+    }
+}
+
+// Crate Private
+pub fn trigger(udev: &Udev, syspaths: Vec<Path>, trigger: &Trigger) -> Result<(), Error> {
+    let timeout = match trigger.wait {
+        Some(timeout) => timeout,
+        None => {
+            let mut errors = Vec::new();
+            for syspath in syspaths.iter() {
+                write_each(trigger, syspath, &mut errors);
+            }
+            return errors_to_result(errors);
+        }
+    };
+
+    let deadline = util::monotonic_now() + timeout;
+    // Listen before triggering so the events can't be missed.
+    let monitor = try!(udev.monitor_with_mode(monitor::Mode::NonBlocking));
+    try!(monitor.enable_receiving());
+
+    let mut pending = HashSet::new();
+    let mut errors = Vec::new();
+    for syspath in syspaths.into_iter() {
+        if write_each(trigger, &syspath, &mut errors) {
+            pending.insert(syspath.into_vec());
+        }
+    }
+    try!(wait(udev, &monitor, trigger, pending, deadline));
+    errors_to_result(errors)
+}
+
+// Trigger one device of a set. Devices that vanished since they were listed are skipped (like
+// `udevadm trigger`) and other failures are collected. Returns whether the device was triggered.
+fn write_each(trigger: &Trigger, syspath: &Path, errors: &mut Vec<Error>) -> bool {
+    match trigger.write(syspath) {
+        Ok(()) => true,
+        Err(ref e) if e.errno() == Some(ENOENT) || e.errno() == Some(ENODEV) => false,
+        Err(e) => {
+            errors.push(e);
+            false
+        }
+    }
+}
+
+fn errors_to_result(errors: Vec<Error>) -> Result<(), Error> {
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Multiple { op: "trigger", errors: errors })
+    }
+}
+
+// Wait for the events of the triggered devices.
+fn wait(udev: &Udev, monitor: &monitor::Monitor, trigger: &Trigger,
+        mut pending: HashSet<Vec<u8>>, deadline: Duration) -> Result<(), Error> {
+    while !pending.is_empty() {
+        let remaining = deadline - util::monotonic_now();
+        if remaining <= Duration::zero() {
+            return Err(Error::Timeout { op: "trigger" });
+        }
+        match monitor.receive_timeout(remaining) {
+            Ok(Some((event, dev))) => if event.synth_uuid == trigger.uuid {
+                pending.remove(&dev.syspath_bytes().to_vec());
+            },
+            Ok(None) => (),
+            // Our events may have been lost; fall back to waiting for the whole queue.
+            Err(Error::Overflow) => return udev.settle(remaining),
+            Err(e) => return Err(e)
+        }
+    }
+    Ok(())
+}
+
+fn random_uuid() -> String {
+    let (high, low) = (rand::random::<u64>(), rand::random::<u64>());
+    // Version 4 (random), variant 1.
+    let high = (high & !0xf000) | 0x4000;
+    let low = (low & !(0xc << 60)) | (0x8 << 60);
+    format!("{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+            high >> 32, (high >> 16) & 0xffff, high & 0xffff,
+            low >> 48, low & 0xffffffffffff)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{File, TempDir, USER_RWX};
+    use std::io::fs;
+
+    use udev::event::Action;
+    use super::Trigger;
+
+    fn write(trigger: &Trigger) -> String {
+        let dir = TempDir::new("udev-rs-trigger").unwrap();
+        let syspath = dir.path().join("devices/virtual/block/loop0");
+        fs::mkdir_recursive(&syspath, USER_RWX).unwrap();
+        File::create(&syspath.join("uevent")).unwrap();
+        trigger.write(&syspath).unwrap();
+        File::open(&syspath.join("uevent")).read_to_string().unwrap()
+    }
+
+    #[test]
+    fn test_write() {
+        assert_eq!(write(&Trigger::new(Action::Change)).as_slice(), "change");
+        assert_eq!(write(&Trigger::new(Action::Add).uuid("1b4e28ba-2fa1-11d2-883f-0016d3cca427")).as_slice(),
+                   "add 1b4e28ba-2fa1-11d2-883f-0016d3cca427");
+        assert_eq!(write(&Trigger::new(Action::Remove)
+                         .uuid("1b4e28ba-2fa1-11d2-883f-0016d3cca427")
+                         .arg("REASON", "test")).as_slice(),
+                   "remove 1b4e28ba-2fa1-11d2-883f-0016d3cca427 REASON=test");
+    }
+
+    #[test]
+    fn test_generated_uuid() {
+        let trigger = Trigger::new(Action::Change).arg("A", "1");
+        let uuid = trigger.get_uuid().unwrap().to_string();
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(uuid.as_slice().char_at(14), '4');
+        assert_eq!(write(&trigger), format!("change {} A=1", uuid));
+    }
+
+    #[test]
+    fn test_missing_uevent() {
+        let dir = TempDir::new("udev-rs-trigger").unwrap();
+        assert!(Trigger::new(Action::Change).write(dir.path()).is_err());
+    }
+
+    #[test]
+    fn test_skip_vanished() {
+        use udev::error::Error;
+
+        let dir = TempDir::new("udev-rs-trigger").unwrap();
+        let present = dir.path().join("devices/virtual/block/loop0");
+        fs::mkdir_recursive(&present, USER_RWX).unwrap();
+        File::create(&present.join("uevent")).unwrap();
+        let vanished = dir.path().join("devices/virtual/block/loop1");
+
+        let trigger = Trigger::new(Action::Change);
+        let mut errors = Vec::new();
+        assert!(super::write_each(&trigger, &present, &mut errors));
+        assert!(!super::write_each(&trigger, &vanished, &mut errors));
+        assert!(errors.is_empty());
+        assert_eq!(super::errors_to_result(errors), Ok(()));
+
+        let errors = vec![Error::Timeout { op: "write" }];
+        match super::errors_to_result(errors) {
+            Err(Error::Multiple { op: "trigger", ref errors }) => assert_eq!(errors.len(), 1),
+            other => panic!("unexpected result: {}", other),
+        }
+    }
+}
@@ -14,82 +14,216 @@
 // 
 // You should have received a copy of the GNU Lesser General Public License
 // along with udev-rs; If not, see <http://www.gnu.org/licenses/>.
+use std::kinds::marker::{NoSend, NoSync};
+use std::time::Duration;
+#[cfg(feature = "libudev")]
+use std::os::unix::AsRawFd;
+#[cfg(feature = "sysfs")]
+use std::os::unix::Fd;
 
-use std::kinds::marker::NoSync;
-use std::io::IoError;
-
+#[cfg(feature = "libudev")]
 use libc::{
     fcntl,
     O_NONBLOCK,
     F_SETFL,
     F_GETFL,
     ENOMEM,
-    EINVAL,
 };
+#[cfg(all(feature = "libudev", feature = "sysfs"))]
+use libc::EOPNOTSUPP;
+#[cfg(feature = "libudev")]
 use alloc::oom;
 
 use udev::{
     device,
+    error,
     util,
-    hwdb,
     monitor,
     enumerator,
+    filter,
+    queue,
+};
+#[cfg(feature = "libudev")]
+use udev::{
+    hwdb,
     libudev_c,
 };
 use udev::device::{
     Device,
 };
+use udev::error::Error;
+use udev::builder::UdevBuilder;
+#[cfg(feature = "libudev")]
 use udev::hwdb::Hwdb;
 use udev::monitor::Monitor;
+use udev::event::Action;
 use udev::enumerator::Enumerator;
+use udev::filter::Filter;
+use udev::queue::Queue;
+#[cfg(feature = "sysfs")]
+use udev::sysfs::{Sysfs, Scan};
+#[cfg(feature = "sysfs")]
+use udev::netlink::{NetlinkMonitor, Group};
 
+/// A udev context.
+///
+/// A context either wraps a libudev context (the `libudev` feature) or reads sysfs and udev's
+/// database directly (the `sysfs` feature, see `UdevBuilder`). Devices, enumerators, monitors
+/// and queues have the same API with either backend.
+///
+/// Contexts are reference counted: cloning a context is cheap and the clone refers to the same
+/// underlying context. Devices, enumerators, monitors and hardware databases each hold a handle
+/// to their context so they can be stored without borrowing it.
 pub struct Udev {
-    // Not thread safe. As all children will hold a reference, this makes everything safe.
+    // Not thread safe (the reference count isn't atomic). As all children will hold a handle, this
+    // makes everything safe.
+    nosend: NoSend,
     nosync: NoSync,
-    udev: libudev_c::udev
+    context: Context,
+}
+
+// Crate Private
+pub enum Context {
+    #[cfg(feature = "libudev")]
+    Libudev(libudev_c::udev),
+    #[cfg(feature = "sysfs")]
+    Sysfs(Sysfs),
+}
+
+pub fn from_context(context: Context) -> Udev {
+    Udev { nosend: NoSend, nosync: NoSync, context: context }
+}
+
+#[cfg(feature = "libudev")]
+pub fn libudev_context() -> Result<Context, Error> {
+    match util::check_errno(|| unsafe { libudev_c::udev_new() }) {
+        Ok(Some(udev))  => Ok(Context::Libudev(udev)),
+        Ok(None)        => Err(error::unknown("udev_new", None)),
+        Err(e)          => Err(error::os(e, "udev_new", None))
+    }
+}
+
+// The roots devices built from event properties are read from: the context's own for the sysfs
+// backend and the real paths for libudev.
+#[cfg(feature = "sysfs")]
+pub fn udev_get_sysfs(udev: &Udev) -> Sysfs {
+    match udev.context {
+        #[cfg(feature = "libudev")]
+        Context::Libudev(_) => Sysfs::new(),
+        Context::Sysfs(ref sysfs) => sysfs.clone(),
+    }
+}
+
+pub fn factory(builder: UdevBuilder) -> UdevFactory {
+    UdevFactory { builder: builder }
+}
+
+/// A factory for udev contexts.
+///
+/// Unlike a `Udev` context, a factory can be shared between and sent to other threads. Each thread
+/// that needs to talk to udev should create its own context with `create`. Contexts created by a
+/// factory read devices from the paths of the `UdevBuilder` it was created with (see
+/// `UdevBuilder::factory`).
+#[deriving(Clone)]
+pub struct UdevFactory {
+    builder: UdevBuilder,
+}
+
+impl UdevFactory {
+    /// Create a new factory for contexts with the default paths.
+    pub fn new() -> UdevFactory {
+        UdevBuilder::new().factory()
+    }
+
+    /// Create a new udev context for the current thread.
+    pub fn create(&self) -> Result<Udev, Error> {
+        self.builder.build()
+    }
 }
 
 impl Udev {
     /// Create a new udev handle.
+    ///
+    /// # Failure
+    ///
+    /// Panics if libudev can't create a context. Use `try_new` to handle this case.
     pub fn new() -> Udev {
-        let udev = unsafe { libudev_c::udev_new() };
-        // I don't care about errno. NULL == oom.
-        if udev.is_null() {
-            oom();
+        match Udev::try_new() {
+            Ok(udev) => udev,
+            Err(e) => panic!("failed to create a udev context: {}", e)
         }
-        Udev { nosync: NoSync, udev: udev }
-    }
-
-    fn create_monitor(&self, name: &str) -> Result<Monitor, IoError>  {
-        let monitor = match name.with_c_str(|name| util::check_errno(|| unsafe {
-            libudev_c::udev_monitor_new_from_netlink(self.udev, name)
-        })) {
-            Ok(Some(monitor))       => monitor,
-            Err(EINVAL) | Ok(None)  => panic!("BUG"),
-            Err(e)                  => return Err(IoError::from_errno(e as uint, true))
-        };
-        let fd = unsafe {
-            libudev_c::udev_monitor_get_fd(monitor)
-        };
-
-        let old_val = unsafe { fcntl(fd, F_GETFL) };
-        if old_val == -1 || unsafe { fcntl(fd, F_SETFL, old_val & !O_NONBLOCK) == -1 } {
-            return match util::get_errno() {
-                ENOMEM | EINVAL => panic!("BUG"),
-                e => Err(IoError::from_errno(e as uint, true))
+    }
+
+    /// Create a new udev handle, reporting failures.
+    ///
+    /// The context uses libudev if the `libudev` feature is enabled and the pure-Rust backend
+    /// otherwise.
+    ///
+    /// # Error
+    ///
+    /// This will return an error if libudev can't create a context (e.g. in a container without
+    /// access to `/sys`).
+    pub fn try_new() -> Result<Udev, Error> {
+        UdevBuilder::new().build()
+    }
+
+    fn create_monitor(&self, name: &str, mode: monitor::Mode) -> Result<Monitor, Error>  {
+        match self.context {
+            #[cfg(feature = "libudev")]
+            Context::Libudev(udev) => {
+                let monitor = match name.with_c_str(|c_name| util::check_errno(|| unsafe {
+                    libudev_c::udev_monitor_new_from_netlink(udev, c_name)
+                })) {
+                    Ok(Some(monitor))   => unsafe { monitor::monitor(self, monitor) },
+                    Ok(None)            => return Err(error::unknown("udev_monitor_new_from_netlink", Some(name))),
+                    Err(e)              => return Err(error::os(e, "udev_monitor_new_from_netlink", Some(name)))
+                };
+                // From here on, dropping the monitor will free it.
+                let fd = monitor.as_raw_fd();
+
+                let old_val = unsafe { fcntl(fd, F_GETFL) };
+                let new_val = match mode {
+                    monitor::Mode::Blocking     => old_val & !O_NONBLOCK,
+                    monitor::Mode::NonBlocking  => old_val | O_NONBLOCK,
+                };
+                if old_val == -1 || unsafe { fcntl(fd, F_SETFL, new_val) == -1 } {
+                    return match util::get_errno() {
+                        ENOMEM  => oom(),
+                        e       => Err(error::os(e, "fcntl", Some(name)))
+                    }
+                }
+
+                Ok(monitor)
+            },
+            #[cfg(feature = "sysfs")]
+            Context::Sysfs(_) => {
+                let group = if name == "kernel" { Group::Kernel } else { Group::Udev };
+                NetlinkMonitor::new(self, group).map(|netlink| monitor::netlink_monitor(self, netlink))
             }
         }
-
-        Ok(unsafe { monitor::monitor(self, monitor) })
     }
 
     /// Monitor udev events.
     ///
+    /// The returned monitor blocks while waiting for events (see `monitor_with_mode`).
+    ///
+    /// # Error
+    ///
+    /// This will return an error if you're running in an environment without access to netlink.
+    pub fn monitor(&self) -> Result<Monitor, Error> {
+        self.create_monitor("udev", monitor::Mode::Blocking)
+    }
+
+    /// Monitor udev events in the specified mode.
+    ///
+    /// Non-blocking monitors are meant to be used with an event loop: wait for the monitor's file
+    /// descriptor (`as_raw_fd`) to become readable and then call `try_receive`.
+    ///
     /// # Error
     ///
     /// This will return an error if you're running in an environment without access to netlink.
-    pub fn monitor(&self) -> Result<Monitor, IoError> {
-        self.create_monitor("udev")
+    pub fn monitor_with_mode(&self, mode: monitor::Mode) -> Result<Monitor, Error> {
+        self.create_monitor("udev", mode)
     }
 
     /// Monitor kernel events.
@@ -111,71 +245,272 @@ impl Udev {
     /// > are sent out after udev has finished its event processing,
     /// > all rules have been processed, and needed device nodes are
     /// > created.
-    pub unsafe fn monitor_kernel(&self) -> Result<Monitor, IoError> {
-        self.create_monitor("kernel")
+    pub unsafe fn monitor_kernel(&self) -> Result<Monitor, Error> {
+        self.create_monitor("kernel", monitor::Mode::Blocking)
+    }
+
+    /// Monitor kernel events in the specified mode.
+    ///
+    /// See `monitor_kernel` for why this method is unsafe and `monitor_with_mode` for a
+    /// description of the modes.
+    pub unsafe fn monitor_kernel_with_mode(&self, mode: monitor::Mode) -> Result<Monitor, Error> {
+        self.create_monitor("kernel", mode)
+    }
+
+    /// Monitor udev events arriving on the specified socket.
+    ///
+    /// Takes ownership of the socket, which must carry events in the udev group's format (e.g.
+    /// the other end of a socketpair passed to `MonitorSender::from_fd`). Like monitors of sysfs
+    /// contexts, the returned monitor is backed by a `NetlinkMonitor` and never blocks. Works
+    /// with either backend: devices are built from the received properties.
+    ///
+    /// # Error
+    ///
+    /// This will return an error if the socket can't be made non-blocking.
+    #[cfg(feature = "sysfs")]
+    pub fn monitor_from_fd(&self, fd: Fd) -> Result<Monitor, Error> {
+        NetlinkMonitor::from_fd(self, fd, Group::Udev).map(|netlink| monitor::netlink_monitor(self, netlink))
     }
 
     /// Create a new hardware database handle.
     ///
     /// # Error
     ///
-    /// On error, this method will return either Err(errno) or Err(0). Err(errno) indicates a
-    /// problem reading the hardware database and Err(0) indicates that the hardware database is
-    /// corrupt.
-    pub fn hwdb(&self) -> Result<Hwdb, i32> {
-        match util::check_errno(|| unsafe {
-            libudev_c::udev_hwdb_new(self.udev)
-        }) {
-            Ok(Some(hwdb))  => Ok(unsafe { hwdb::hwdb(self, hwdb) }),
-            Ok(None)        => Err(0i32),
-            Err(EINVAL)     => panic!("BUG"),
-            Err(e)          => Err(e)
+    /// An `Error::Os` indicates a problem reading the hardware database and an `Error::Unknown`
+    /// indicates that the hardware database is corrupt. The pure-Rust backend has no hardware
+    /// database (`EOPNOTSUPP`).
+    #[cfg(feature = "libudev")]
+    pub fn hwdb(&self) -> Result<Hwdb, Error> {
+        match self.context {
+            Context::Libudev(udev) => match util::check_errno(|| unsafe {
+                libudev_c::udev_hwdb_new(udev)
+            }) {
+                Ok(Some(hwdb))  => Ok(unsafe { hwdb::hwdb(self, hwdb) }),
+                Ok(None)        => Err(error::unknown("udev_hwdb_new", None)),
+                Err(e)          => Err(error::os(e, "udev_hwdb_new", None))
+            },
+            #[cfg(feature = "sysfs")]
+            Context::Sysfs(_) => Err(error::os(EOPNOTSUPP, "udev_hwdb_new", None))
+        }
+    }
+
+    /// Get a handle to the udev event queue.
+    pub fn queue(&self) -> Result<Queue, Error> {
+        match self.context {
+            #[cfg(feature = "libudev")]
+            Context::Libudev(udev) => match util::check_errno(|| unsafe {
+                libudev_c::udev_queue_new(udev)
+            }) {
+                Ok(Some(queue)) => Ok(unsafe { queue::queue(self, queue) }),
+                Ok(None)        => Err(error::unknown("udev_queue_new", None)),
+                Err(e)          => Err(error::os(e, "udev_queue_new", None))
+            },
+            #[cfg(feature = "sysfs")]
+            Context::Sysfs(ref sysfs) => Ok(queue::sysfs_queue(self, sysfs.run_dir()))
         }
     }
 
+    /// Wait until the udev daemon has finished processing all queued events.
+    ///
+    /// See `Queue::settle`.
+    pub fn settle(&self, timeout: Duration) -> Result<(), Error> {
+        self.queue().and_then(|queue| queue.settle(timeout))
+    }
+
     /// Lookup a device by sys path.
     pub fn device(&self, path: &Path) -> Option<Device> {
-        match path.with_c_str(|path| util::check_errno(|| unsafe {
-            libudev_c::udev_device_new_from_syspath(self.udev, path)
-        })) {
-            Ok(Some(dev)) => Some(unsafe { device::device(self, dev) }),
-            _ => None
+        match self.context {
+            #[cfg(feature = "libudev")]
+            Context::Libudev(udev) => match path.with_c_str(|path| util::check_errno(|| unsafe {
+                libudev_c::udev_device_new_from_syspath(udev, path)
+            })) {
+                Ok(Some(dev)) => Some(unsafe { device::device(self, dev) }),
+                _ => None
+            },
+            #[cfg(feature = "sysfs")]
+            Context::Sysfs(ref sysfs) => sysfs.device(path).ok().map(|dev| device::sysfs_device(self, dev))
         }
     }
 
     /// Lookup a device by device type and device number.
     pub fn device_from_devnum(&self, ty: device::Type, devnum: device::Devnum) -> Option<Device> {
-        match util::check_errno(|| unsafe {
-            libudev_c::udev_device_new_from_devnum(self.udev, ty.to_char(), devnum)
-        }) {
-            Ok(Some(dev)) => Some(unsafe { device::device(self, dev) }),
-            _ => None
+        match self.context {
+            #[cfg(feature = "libudev")]
+            Context::Libudev(udev) => match util::check_errno(|| unsafe {
+                libudev_c::udev_device_new_from_devnum(udev, ty.to_char(), devnum.to_raw())
+            }) {
+                Ok(Some(dev)) => Some(unsafe { device::device(self, dev) }),
+                _ => None
+            },
+            #[cfg(feature = "sysfs")]
+            Context::Sysfs(ref sysfs) => {
+                sysfs.device_from_devnum(ty, devnum).map(|dev| device::sysfs_device(self, dev))
+            }
         }
     }
 
     /// Lookup a device by subsystem and sysname
     pub fn device_from_subsystem_sysname(&self, subsystem: &str, sysname: &str) -> Option<Device> {
-        match subsystem.with_c_str(|subsystem| sysname.with_c_str(|sysname| util::check_errno(|| unsafe {
-            libudev_c::udev_device_new_from_subsystem_sysname(self.udev, subsystem, sysname)
-        }))) {
-            Ok(Some(dev)) => Some(unsafe { device::device(self, dev) }),
-            _ => None
+        match self.context {
+            #[cfg(feature = "libudev")]
+            Context::Libudev(udev) => match subsystem.with_c_str(|subsystem| sysname.with_c_str(|sysname| {
+                util::check_errno(|| unsafe {
+                    libudev_c::udev_device_new_from_subsystem_sysname(udev, subsystem, sysname)
+                })
+            })) {
+                Ok(Some(dev)) => Some(unsafe { device::device(self, dev) }),
+                _ => None
+            },
+            #[cfg(feature = "sysfs")]
+            Context::Sysfs(ref sysfs) => {
+                sysfs.device_from_subsystem_sysname(subsystem, sysname).map(|dev| device::sysfs_device(self, dev))
+            }
+        }
+    }
+
+    /// Lookup a device by device id.
+    ///
+    /// Device ids are the names used by udev's database (`/run/udev/data`) and tags:
+    ///
+    /// * `b8:0` - block device 8:0
+    /// * `c189:1` - character device 189:1
+    /// * `n3` - network interface 3
+    /// * `+usb:1-1` - the `1-1` device in the `usb` subsystem
+    ///
+    /// # Error
+    ///
+    /// Returns an error if the id is malformed or the device doesn't exist.
+    pub fn device_from_device_id(&self, id: &str) -> Result<Device, Error> {
+        match self.context {
+            #[cfg(feature = "libudev")]
+            Context::Libudev(udev) => match id.with_c_str(|c_id| util::check_errno(|| unsafe {
+                libudev_c::udev_device_new_from_device_id(udev, c_id)
+            })) {
+                Ok(Some(dev))   => Ok(unsafe { device::device(self, dev) }),
+                Ok(None)        => Err(error::unknown("udev_device_new_from_device_id", Some(id))),
+                Err(e)          => Err(error::os(e, "udev_device_new_from_device_id", Some(id)))
+            },
+            #[cfg(feature = "sysfs")]
+            Context::Sysfs(ref sysfs) => {
+                sysfs.device_from_device_id(id).map(|dev| device::sysfs_device(self, dev))
+            }
+        }
+    }
+
+    /// Create a device from the current process's environment.
+    ///
+    /// This is meant for programs run by udev rules (`RUN+=`, `IMPORT{program}=`, etc.) where udev
+    /// passes the device's properties as environment variables.
+    ///
+    /// # Error
+    ///
+    /// Returns an error if the environment doesn't describe a device (i.e. the program wasn't run
+    /// by udev).
+    pub fn device_from_environment(&self) -> Result<Device, Error> {
+        match self.context {
+            #[cfg(feature = "libudev")]
+            Context::Libudev(udev) => match util::check_errno(|| unsafe {
+                libudev_c::udev_device_new_from_environment(udev)
+            }) {
+                Ok(Some(dev))   => Ok(unsafe { device::device(self, dev) }),
+                Ok(None)        => Err(error::unknown("udev_device_new_from_environment", None)),
+                Err(e)          => Err(error::os(e, "udev_device_new_from_environment", None))
+            },
+            #[cfg(feature = "sysfs")]
+            Context::Sysfs(ref sysfs) => sysfs.from_environment().map(|dev| device::sysfs_device(self, dev))
+        }
+    }
+
+    /// Wait until an initialized device matching `filter` exists.
+    ///
+    /// A monitor is started before existing devices are checked so a device that appears in the
+    /// meantime can't be missed. E.g. to wait for a filesystem:
+    ///
+    /// ```ignore
+    /// let filter = Filter::new().match_devlink(&Path::new("/dev/disk/by-uuid/1234-ABCD"));
+    /// let dev = try!(udev.wait_for_device(&filter, Duration::seconds(10)));
+    /// ```
+    ///
+    /// # Error
+    ///
+    /// Returns an `Error::Timeout` if no such device appears within `timeout` or an error if the
+    /// device can't be monitored or enumerated.
+    pub fn wait_for_device(&self, filter: &Filter, timeout: Duration) -> Result<Device, Error> {
+        let deadline = util::monotonic_now() + timeout;
+        let monitor = try!(self.monitor_with_mode(monitor::Mode::NonBlocking)
+                           .and_then(|m| m.filter_by(filter.clone())));
+        try!(monitor.enable_receiving());
+
+        let mut rescan = true;
+        loop {
+            if rescan {
+                if let Some(dev) = try!(self.find_initialized(filter)) {
+                    return Ok(dev);
+                }
+                rescan = false;
+            }
+            let remaining = deadline - util::monotonic_now();
+            if remaining <= Duration::zero() {
+                return Err(Error::Timeout { op: "wait_for_device" });
+            }
+            match monitor.receive_timeout(remaining) {
+                Ok(Some((event, dev))) => {
+                    if event.action != Action::Remove && dev.is_initialized() {
+                        return Ok(dev);
+                    }
+                },
+                Ok(None) => (),
+                // Events were lost so the device may have appeared unnoticed.
+                Err(Error::Overflow) => rescan = true,
+                Err(e) => return Err(e)
+            }
+        }
+    }
+
+    fn find_initialized(&self, filter: &Filter) -> Result<Option<Device>, Error> {
+        let mut enumerator = try!(self.enumerator());
+        for subsystem in filter::filter_subsystems(filter).iter() {
+            enumerator = try!(enumerator.match_subsystem(subsystem.as_slice()));
         }
+        enumerator = try!(enumerator.match_is_initialized().and_then(|e| e.scan_devices()));
+        Ok(enumerator.iter().find(|dev| filter.matches(dev)))
     }
 
     /// Create a device enumerator.
-    pub fn enumerator(&self) -> Enumerator {
-        unsafe {
-            enumerator::enumerator(
-                self, util::check_errno(|| {
-                    libudev_c::udev_enumerate_new(self.udev)
-                }).unwrap().unwrap())
+    pub fn enumerator(&self) -> Result<Enumerator, Error> {
+        match self.context {
+            #[cfg(feature = "libudev")]
+            Context::Libudev(udev) => match util::check_errno(|| unsafe {
+                libudev_c::udev_enumerate_new(udev)
+            }) {
+                Ok(Some(e)) => Ok(unsafe { enumerator::enumerator(self, e) }),
+                Ok(None)    => Err(error::unknown("udev_enumerate_new", None)),
+                Err(e)      => Err(error::os(e, "udev_enumerate_new", None))
+            },
+            #[cfg(feature = "sysfs")]
+            Context::Sysfs(ref sysfs) => Ok(enumerator::sysfs_enumerator(self, Scan::new(sysfs.clone())))
         }
     }
 }
 
+impl Clone for Udev {
+    fn clone(&self) -> Udev {
+        from_context(match self.context {
+            #[cfg(feature = "libudev")]
+            Context::Libudev(udev) => Context::Libudev(unsafe { libudev_c::udev_ref(udev) }),
+            #[cfg(feature = "sysfs")]
+            Context::Sysfs(ref sysfs) => Context::Sysfs(sysfs.clone()),
+        })
+    }
+}
+
 impl Drop for Udev {
     fn drop(&mut self) {
-        unsafe { libudev_c::udev_unref(self.udev) };
+        match self.context {
+            #[cfg(feature = "libudev")]
+            Context::Libudev(udev) => unsafe { libudev_c::udev_unref(udev); },
+            #[cfg(feature = "sysfs")]
+            Context::Sysfs(_) => ()
+        }
     }
 }
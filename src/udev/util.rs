@@ -16,12 +16,21 @@
 // along with udev-rs; If not, see <http://www.gnu.org/licenses/>.
 
 use std::raw::Slice;
-use std::{mem, ptr, str};
+use std::io::{IoError, FileNotFound, PermissionDenied};
+use std::{mem, ptr, str, i32};
+use std::num::from_str_radix;
+use std::time::Duration;
 
-use libc::{ENOMEM, c_int, c_char};
+use libc::{ENOMEM, EINTR, EIO, ENOENT, EACCES, O_WRONLY, O_TRUNC, c_int, c_char, c_short, c_long, c_ulong, c_void, size_t, time_t};
+use libc::{open, write, close};
 use alloc::oom;
 
-pub unsafe fn c_to_str<'a>(s: *const c_char) -> Option<&'a str> {
+use udev::error;
+use udev::error::Error;
+#[cfg(test)]
+use udev::snapshot::DeviceSnapshot;
+
+pub unsafe fn c_to_bytes<'a>(s: *const c_char) -> Option<&'a [u8]> {
     if s.is_null() {
         None
     } else {
@@ -31,16 +40,57 @@ pub unsafe fn c_to_str<'a>(s: *const c_char) -> Option<&'a str> {
             len += 1;
             cur = cur.offset(1);
         }
-        let slice = mem::transmute(Slice { data: s, len: len });
-        str::from_utf8(slice)
+        Some(mem::transmute(Slice { data: s, len: len }))
     }
 }
 
-pub fn handle_error(err: i32) {
+pub unsafe fn c_to_str<'a>(s: *const c_char) -> Option<&'a str> {
+    c_to_bytes(s).and_then(|bytes| str::from_utf8(bytes))
+}
+
+pub fn lossy(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_string()
+}
+
+pub fn parse_bool(s: &str) -> Option<bool> {
+    match s.trim() {
+        "1" => Some(true),
+        "0" => Some(false),
+        _   => None
+    }
+}
+
+pub fn parse_hex(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let digits = if s.starts_with("0x") || s.starts_with("0X") {
+        s.slice_from(2)
+    } else {
+        s
+    };
+    from_str_radix(digits, 16)
+}
+
+pub fn trim_end(value: &[u8]) -> &[u8] {
+    let end = value.iter().rposition(|&b| b != b' ' && b != b'\n' && b != b'\t').map_or(0, |i| i + 1);
+    value.slice_to(end)
+}
+
+pub fn io_error(err: &IoError, op: &'static str, path: &Path) -> Error {
+    // IoErrors don't keep the errno.
+    let errno = match err.kind {
+        FileNotFound        => ENOENT,
+        PermissionDenied    => EACCES,
+        _                   => EIO
+    };
+    error::os(errno, op, Some(path.display().to_string().as_slice()))
+}
+
+pub fn handle_error(err: c_int, op: &'static str, object: Option<&str>) -> Result<(), Error> {
     match err {
-        0 => (),
+        0 => Ok(()),
         x if x == -ENOMEM => oom(),
-        _ => panic!("Unhandled udev error.")
+        x if x < 0 => Err(error::os(-x, op, object)),
+        _ => Err(error::unknown(op, object))
     }
 }
 
@@ -80,3 +130,131 @@ pub fn check_errno<I, T: ptr::RawPtr<I>>(f: || -> T) -> Result<Option<T>, c_int>
     }
 }
 
+#[repr(C)]
+struct pollfd {
+    fd: c_int,
+    events: c_short,
+    revents: c_short,
+}
+
+#[repr(C)]
+struct timespec {
+    tv_sec: time_t,
+    tv_nsec: c_long,
+}
+
+const POLLIN: c_short = 0x1;
+const CLOCK_REALTIME: c_int = 0;
+const CLOCK_MONOTONIC: c_int = 1;
+
+extern {
+    fn poll(fds: *mut pollfd, nfds: c_ulong, timeout: c_int) -> c_int;
+    fn clock_gettime(clock: c_int, tp: *mut timespec) -> c_int;
+}
+
+/// Wait until `fd` is readable or the timeout expires (`None` waits forever).
+///
+/// Returns `Ok(false)` on timeout (or if interrupted by a signal) and `Err(errno)` on error.
+pub fn wait_readable(fd: c_int, timeout: Option<Duration>) -> Result<bool, c_int> {
+    let timeout = match timeout {
+        Some(t) if t.num_milliseconds() > i32::MAX as i64 => i32::MAX as c_int,
+        Some(t) if t.num_milliseconds() < 0 => 0,
+        Some(t) => t.num_milliseconds() as c_int,
+        None => -1,
+    };
+    let mut fds = pollfd { fd: fd, events: POLLIN, revents: 0 };
+    match unsafe { poll(&mut fds, 1, timeout) } {
+        -1 => match get_errno() {
+            EINTR => Ok(false),
+            e => Err(e)
+        },
+        0 => Ok(false),
+        _ => Ok(true)
+    }
+}
+
+/// Check whether `fd` is readable without waiting.
+///
+/// Unlike `wait_readable`, this retries when interrupted by a signal so `Ok(false)` always means
+/// there's nothing to read.
+pub fn is_readable(fd: c_int) -> Result<bool, c_int> {
+    let mut fds = pollfd { fd: fd, events: POLLIN, revents: 0 };
+    loop {
+        match unsafe { poll(&mut fds, 1, 0) } {
+            -1 => match get_errno() {
+                EINTR => (),
+                e => return Err(e)
+            },
+            0 => return Ok(false),
+            _ => return Ok(true)
+        }
+    }
+}
+
+fn clock_now(clock: c_int) -> Duration {
+    let mut ts = timespec { tv_sec: 0, tv_nsec: 0 };
+    // Both clocks are always available on linux.
+    unsafe { clock_gettime(clock, &mut ts) };
+    Duration::seconds(ts.tv_sec as i64) + Duration::nanoseconds(ts.tv_nsec as i64)
+}
+
+/// Get the current time of the monotonic clock.
+pub fn monotonic_now() -> Duration {
+    clock_now(CLOCK_MONOTONIC)
+}
+
+/// Get the current wall-clock time (since the Unix epoch).
+pub fn realtime_now() -> Duration {
+    clock_now(CLOCK_REALTIME)
+}
+
+/// Write `data` to an existing file in a single `write` (as sysfs requires).
+///
+/// Returns `Err(errno)` on error.
+pub fn write_file(path: &Path, data: &[u8]) -> Result<(), c_int> {
+    let fd = path.with_c_str(|path| unsafe { open(path, O_WRONLY | O_TRUNC, 0) });
+    if fd < 0 {
+        return Err(get_errno());
+    }
+    let written = unsafe { write(fd, data.as_ptr() as *const c_void, data.len() as size_t) };
+    let result = if written < 0 {
+        Err(get_errno())
+    } else if written as uint != data.len() {
+        Err(EIO)
+    } else {
+        Ok(())
+    };
+    unsafe { close(fd) };
+    result
+}
+
+// Test helpers
+
+#[cfg(test)]
+extern {
+    fn socketpair(domain: c_int, ty: c_int, protocol: c_int, fds: *mut c_int) -> c_int;
+}
+
+/// Create a connected pair of unix datagram sockets (e.g. to stand in for a netlink socket).
+#[cfg(test)]
+pub fn socket_pair() -> (c_int, c_int) {
+    const AF_UNIX: c_int = 1;
+    const SOCK_DGRAM: c_int = 2;
+
+    let mut fds = [0 as c_int, ..2];
+    assert_eq!(unsafe { socketpair(AF_UNIX, SOCK_DGRAM, 0, fds.as_mut_ptr()) }, 0);
+    (fds[0], fds[1])
+}
+
+/// Create a snapshot of a device under `/sys` from its devpath, subsystem and other properties.
+#[cfg(test)]
+pub fn snapshot(devpath: &str, subsystem: &str, properties: &[(&str, &str)]) -> DeviceSnapshot {
+    let mut all = vec![
+        (b"DEVPATH".to_vec(), devpath.as_bytes().to_vec()),
+        (b"SUBSYSTEM".to_vec(), subsystem.as_bytes().to_vec()),
+    ];
+    for &(key, value) in properties.iter() {
+        all.push((key.as_bytes().to_vec(), value.as_bytes().to_vec()));
+    }
+    DeviceSnapshot::from_properties(all).unwrap()
+}
@@ -0,0 +1,495 @@
+// This file is part of udev-rs.
+// 
+// Copyright 2014 Steven Allen <steven@stebalien.com>
+// 
+// udev-rs is free software; you can redistribute it and/or modify it
+// under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation; either version 2.1 of the License, or
+// (at your option) any later version.
+// 
+// udev-rs is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Lesser General Public License for more details.
+// 
+// You should have received a copy of the GNU Lesser General Public License
+// along with udev-rs; If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::{HashMap, HashSet, RingBuf};
+
+use udev::udev::Udev;
+use udev::error::Error;
+use udev::monitor::{Monitor, Mode};
+use udev::event::{Event, Action};
+use udev::snapshot::DeviceSnapshot;
+
+// How many seqnums to remember for deduplication.
+const SEEN_CAPACITY: uint = 4096;
+
+/// A source of device events and device scans for a `DeviceWatcher`.
+///
+/// `UdevSource` is the real implementation; other implementations are mostly useful for testing.
+pub trait Source {
+    /// Receive the next queued event without blocking.
+    ///
+    /// Returns `Ok(None)` when no event is queued and `Err(Error::Overflow)` when events have been
+    /// lost.
+    fn receive(&mut self) -> Result<Option<(Event, DeviceSnapshot)>, Error>;
+
+    /// List all devices that currently exist.
+    fn scan(&mut self) -> Result<Vec<DeviceSnapshot>, Error>;
+}
+
+/// A `Source` backed by a non-blocking udev monitor and an enumerator.
+pub struct UdevSource {
+    monitor: Monitor,
+    subsystems: Vec<String>,
+}
+
+impl UdevSource {
+    /// Watch the devices in the specified subsystems (or all devices if none are specified).
+    ///
+    /// The monitor starts receiving events immediately so no event can be missed between the
+    /// initial scan and the first `receive`.
+    pub fn new(udev: &Udev, subsystems: &[&str]) -> Result<UdevSource, Error> {
+        let mut monitor = try!(udev.monitor_with_mode(Mode::NonBlocking));
+        for subsystem in subsystems.iter() {
+            monitor = try!(monitor.filter_by_subsystem(*subsystem));
+        }
+        try!(monitor.enable_receiving());
+        Ok(UdevSource {
+            monitor: monitor,
+            subsystems: subsystems.iter().map(|s| s.to_string()).collect(),
+        })
+    }
+
+    /// Get the underlying monitor (e.g. to wait on its file descriptor).
+    pub fn monitor(&self) -> &Monitor {
+        &self.monitor
+    }
+}
+
+impl Source for UdevSource {
+    fn receive(&mut self) -> Result<Option<(Event, DeviceSnapshot)>, Error> {
+        self.monitor.try_receive().map(|event| event.map(|(event, dev)| (event, dev.snapshot())))
+    }
+
+    fn scan(&mut self) -> Result<Vec<DeviceSnapshot>, Error> {
+        let mut enumerator = try!(self.monitor.udev().enumerator());
+        for subsystem in self.subsystems.iter() {
+            enumerator = try!(enumerator.match_subsystem(subsystem.as_slice()));
+        }
+        enumerator = try!(enumerator.scan_devices());
+        Ok(enumerator.iter().map(|dev| dev.snapshot()).collect())
+    }
+}
+
+/// A change to the set of devices tracked by a `DeviceWatcher`.
+#[deriving(Clone, PartialEq, Eq, Show)]
+pub enum Delta {
+    /// A device appeared.
+    Added(DeviceSnapshot),
+    /// A device changed (the new state).
+    Changed(DeviceSnapshot),
+    /// A device disappeared (the last known state).
+    ///
+    /// A renamed device (a `Move` event) is reported as removed from its old path and added at
+    /// its new one.
+    Removed(DeviceSnapshot),
+}
+
+/// Keeps an up-to-date set of devices.
+///
+/// A watcher combines a monitor with an enumerator without racing: the monitor starts receiving
+/// before the initial scan, events that duplicate the scan are folded into it, and events are
+/// deduplicated by seqnum. When the monitor loses events (`Error::Overflow`), the watcher rescans
+/// and reports the differences as deltas.
+pub struct DeviceWatcher<S> {
+    source: S,
+    devices: HashMap<Path, DeviceSnapshot>,
+    seen: HashSet<u64>,
+    seen_order: RingBuf<u64>,
+    // Whether events were lost and the next `poll` has to rescan first.
+    stale: bool,
+    // An error that happened after some changes were already returned (reported next).
+    pending: Option<Error>,
+}
+
+impl<S: Source> DeviceWatcher<S> {
+    /// Create a new watcher and perform the initial scan.
+    pub fn new(source: S) -> Result<DeviceWatcher<S>, Error> {
+        let mut watcher = DeviceWatcher {
+            source: source,
+            devices: HashMap::new(),
+            seen: HashSet::new(),
+            seen_order: RingBuf::new(),
+            stale: false,
+            pending: None,
+        };
+        try!(watcher.resync());
+        Ok(watcher)
+    }
+
+    /// Get the current set of devices (keyed by syspath).
+    pub fn devices(&self) -> &HashMap<Path, DeviceSnapshot> {
+        &self.devices
+    }
+
+    /// Look up a device by syspath.
+    pub fn get(&self, syspath: &Path) -> Option<&DeviceSnapshot> {
+        self.devices.get(syspath)
+    }
+
+    /// Get the event source.
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+
+    /// Get the event source mutably.
+    pub fn source_mut(&mut self) -> &mut S {
+        &mut self.source
+    }
+
+    /// Process all queued events and return the resulting changes in order.
+    ///
+    /// If events were lost, the watcher rescans and includes the differences in the returned
+    /// changes. If receiving (or rescanning) fails, the changes made so far are returned and the
+    /// error is returned by the next call (immediately if there are no changes). A failed rescan
+    /// is retried by the next call that doesn't return an error.
+    pub fn poll(&mut self) -> Result<Vec<Delta>, Error> {
+        if let Some(e) = self.pending.take() {
+            return Err(e);
+        }
+        let mut deltas = Vec::new();
+        if self.stale {
+            deltas = try!(self.resync());
+        }
+        loop {
+            let result = match self.source.receive() {
+                Ok(Some((event, dev))) => {
+                    self.apply(event, dev, &mut deltas);
+                    continue;
+                },
+                Ok(None) => break,
+                Err(Error::Overflow) => {
+                    self.stale = true;
+                    self.resync()
+                },
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(changes) => deltas.extend(changes.into_iter()),
+                Err(e) => if deltas.is_empty() {
+                    return Err(e);
+                } else {
+                    self.pending = Some(e);
+                    break;
+                }
+            }
+        }
+        Ok(deltas)
+    }
+
+    /// Rescan all devices and return the differences from the current set.
+    ///
+    /// Removals are reported first, followed by additions and changes ordered by syspath. If the
+    /// scan fails, the current set is left as is.
+    pub fn resync(&mut self) -> Result<Vec<Delta>, Error> {
+        let mut scanned: HashMap<Path, DeviceSnapshot> = HashMap::new();
+        for dev in try!(self.source.scan()).into_iter() {
+            scanned.insert(dev.syspath().clone(), dev);
+        }
+
+        let mut removed: Vec<DeviceSnapshot> = self.devices.iter()
+            .filter(|&(path, _)| !scanned.contains_key(path))
+            .map(|(_, dev)| dev.clone())
+            .collect();
+        removed.sort_by(|a, b| a.syspath().as_vec().cmp(b.syspath().as_vec()));
+
+        let mut updated: Vec<Delta> = scanned.iter().filter_map(|(path, dev)| {
+            match self.devices.get(path) {
+                None => Some(Delta::Added(dev.clone())),
+                Some(old) if !same_state(old, dev) => Some(Delta::Changed(dev.clone())),
+                Some(_) => None,
+            }
+        }).collect();
+        updated.sort_by(|a, b| delta_device(a).syspath().as_vec().cmp(delta_device(b).syspath().as_vec()));
+
+        self.devices = scanned;
+        self.stale = false;
+
+        let mut deltas: Vec<Delta> = removed.into_iter().map(Delta::Removed).collect();
+        deltas.extend(updated.into_iter());
+        Ok(deltas)
+    }
+
+    fn apply(&mut self, event: Event, dev: DeviceSnapshot, deltas: &mut Vec<Delta>) {
+        if !self.mark_seen(event.seqnum) {
+            return;
+        }
+        let path = dev.syspath().clone();
+        if event.action == Action::Remove {
+            if let Some(old) = self.devices.remove(&path) {
+                deltas.push(Delta::Removed(old));
+            }
+            return;
+        }
+        // A renamed device disappears from its old path.
+        if let (Action::Move, Some(devpath_old)) = (event.action, event.devpath_old) {
+            if let Some(old_path) = old_syspath(&dev, &devpath_old) {
+                if old_path != path {
+                    if let Some(old) = self.devices.remove(&old_path) {
+                        deltas.push(Delta::Removed(old));
+                    }
+                }
+            }
+        }
+        match self.devices.get(&path) {
+            // Already reflected by the scan (or a duplicate).
+            Some(old) if same_state(old, &dev) => (),
+            Some(_) => deltas.push(Delta::Changed(dev.clone())),
+            None => deltas.push(Delta::Added(dev.clone())),
+        }
+        self.devices.insert(path, dev);
+    }
+
+    // Returns false if this seqnum has already been seen.
+    fn mark_seen(&mut self, seqnum: u64) -> bool {
+        // Events without a seqnum can't be deduplicated.
+        if seqnum == 0 {
+            return true;
+        }
+        if !self.seen.insert(seqnum) {
+            return false;
+        }
+        self.seen_order.push_back(seqnum);
+        if self.seen_order.len() > SEEN_CAPACITY {
+            if let Some(old) = self.seen_order.pop_front() {
+                self.seen.remove(&old);
+            }
+        }
+        true
+    }
+}
+
+// Whether a device is unchanged. Attributes are ignored: some (e.g. `stat`) change constantly.
+fn same_state(a: &DeviceSnapshot, b: &DeviceSnapshot) -> bool {
+    a.properties() == b.properties()
+        && a.tags() == b.tags()
+        && a.devlinks() == b.devlinks()
+        && a.devnode() == b.devnode()
+}
+
+// Get the syspath a moved device had: `devpath_old` under the same sysfs root as `dev`.
+//
+// Returns `None` if `devpath_old` isn't an absolute devpath.
+fn old_syspath(dev: &DeviceSnapshot, devpath_old: &Path) -> Option<Path> {
+    let old = devpath_old.as_vec();
+    let syspath = dev.syspath().as_vec();
+    let devpath = dev.devpath_bytes();
+    if old.len() < 2 || old[0] != b'/' || !syspath.ends_with(devpath) {
+        return None;
+    }
+    let mut old_path = syspath.slice_to(syspath.len() - devpath.len()).to_vec();
+    old_path.push_all(old);
+    Some(Path::new(old_path))
+}
+
+fn delta_device(delta: &Delta) -> &DeviceSnapshot {
+    match *delta {
+        Delta::Added(ref dev) | Delta::Changed(ref dev) | Delta::Removed(ref dev) => dev,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::RingBuf;
+    use std::time::Duration;
+
+    use libc::EIO;
+
+    use udev::error;
+    use udev::error::Error;
+    use udev::event::{Event, Action};
+    use udev::{snapshot, util};
+    use udev::snapshot::DeviceSnapshot;
+    use super::{Source, DeviceWatcher, Delta};
+
+    struct FakeSource {
+        queue: RingBuf<Result<(Event, DeviceSnapshot), Error>>,
+        devices: Vec<DeviceSnapshot>,
+        fail_scan: bool,
+    }
+
+    impl Source for FakeSource {
+        fn receive(&mut self) -> Result<Option<(Event, DeviceSnapshot)>, Error> {
+            match self.queue.pop_front() {
+                Some(Ok(event)) => Ok(Some(event)),
+                Some(Err(e)) => Err(e),
+                None => Ok(None),
+            }
+        }
+
+        fn scan(&mut self) -> Result<Vec<DeviceSnapshot>, Error> {
+            if self.fail_scan {
+                Err(error::os(EIO, "scan", None))
+            } else {
+                Ok(self.devices.clone())
+            }
+        }
+    }
+
+    fn dev(name: &str, serial: &str) -> DeviceSnapshot {
+        util::snapshot(format!("/devices/virtual/test/{}", name).as_slice(), "test", &[("ID_SERIAL", serial)])
+    }
+
+    fn dev_with_stat(name: &str, stat: &str) -> DeviceSnapshot {
+        snapshot::from_parts(&Path::new("/sys"), &Path::new("/dev"), vec![
+            (b"DEVPATH".to_vec(), format!("/devices/virtual/test/{}", name).into_bytes()),
+            (b"SUBSYSTEM".to_vec(), b"test".to_vec()),
+        ], vec![(b"stat".to_vec(), stat.as_bytes().to_vec())]).unwrap()
+    }
+
+    fn event(action: Action, seqnum: u64) -> Event {
+        Event {
+            action: action,
+            seqnum: seqnum,
+            devpath_old: None,
+            driver: None,
+            synth_uuid: None,
+            synth_args: Vec::new(),
+            received: Duration::zero(),
+        }
+    }
+
+    fn watcher(devices: Vec<DeviceSnapshot>) -> DeviceWatcher<FakeSource> {
+        DeviceWatcher::new(FakeSource { queue: RingBuf::new(), devices: devices, fail_scan: false }).unwrap()
+    }
+
+    #[test]
+    fn test_initial_scan() {
+        let watcher = watcher(vec![dev("a", "1"), dev("b", "1")]);
+        assert_eq!(watcher.devices().len(), 2);
+        assert!(watcher.get(&Path::new("/sys/devices/virtual/test/a")).is_some());
+    }
+
+    #[test]
+    fn test_events() {
+        let mut watcher = watcher(vec![dev("a", "1")]);
+        {
+            let queue = &mut watcher.source_mut().queue;
+            // Raced with the scan: already known, nothing changed.
+            queue.push_back(Ok((event(Action::Add, 10), dev("a", "1"))));
+            queue.push_back(Ok((event(Action::Add, 11), dev("b", "1"))));
+            // Duplicate.
+            queue.push_back(Ok((event(Action::Add, 11), dev("b", "1"))));
+            queue.push_back(Ok((event(Action::Change, 12), dev("a", "2"))));
+            queue.push_back(Ok((event(Action::Remove, 13), dev("b", "1"))));
+            // Unknown device.
+            queue.push_back(Ok((event(Action::Remove, 14), dev("c", "1"))));
+        }
+        assert_eq!(watcher.poll().unwrap(), vec![
+            Delta::Added(dev("b", "1")),
+            Delta::Changed(dev("a", "2")),
+            Delta::Removed(dev("b", "1")),
+        ]);
+        assert_eq!(watcher.devices().len(), 1);
+        assert_eq!(watcher.poll().unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_move() {
+        let mut watcher = watcher(vec![dev("a", "1")]);
+        let mut moved = event(Action::Move, 30);
+        moved.devpath_old = Some(Path::new("/devices/virtual/test/a"));
+        watcher.source_mut().queue.push_back(Ok((moved, dev("b", "1"))));
+        assert_eq!(watcher.poll().unwrap(), vec![
+            Delta::Removed(dev("a", "1")),
+            Delta::Added(dev("b", "1")),
+        ]);
+        assert_eq!(watcher.devices().len(), 1);
+    }
+
+    #[test]
+    fn test_move_malformed() {
+        let mut watcher = watcher(vec![dev("a", "1")]);
+        let mut moved = event(Action::Move, 31);
+        moved.devpath_old = Some(Path::new("devices/virtual/test/a"));
+        watcher.source_mut().queue.push_back(Ok((moved, dev("b", "1"))));
+        let mut moved = event(Action::Move, 32);
+        moved.devpath_old = Some(Path::new(""));
+        watcher.source_mut().queue.push_back(Ok((moved, dev("c", "1"))));
+        // The renames are skipped; the new devices are still added.
+        assert_eq!(watcher.poll().unwrap(), vec![
+            Delta::Added(dev("b", "1")),
+            Delta::Added(dev("c", "1")),
+        ]);
+        assert_eq!(watcher.devices().len(), 3);
+    }
+
+    #[test]
+    fn test_attributes_ignored() {
+        let mut watcher = watcher(vec![dev_with_stat("a", "1")]);
+        {
+            let source = watcher.source_mut();
+            source.devices = vec![dev_with_stat("a", "2")];
+            source.queue.push_back(Ok((event(Action::Add, 40), dev_with_stat("a", "3"))));
+        }
+        assert_eq!(watcher.poll().unwrap(), vec![]);
+        assert_eq!(watcher.resync().unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_resync_after_overflow() {
+        let mut watcher = watcher(vec![dev("a", "1"), dev("b", "1"), dev("c", "1")]);
+        {
+            let source = watcher.source_mut();
+            source.devices = vec![dev("b", "2"), dev("c", "1"), dev("d", "1")];
+            source.queue.push_back(Err(Error::Overflow));
+            source.queue.push_back(Ok((event(Action::Add, 20), dev("e", "1"))));
+        }
+        assert_eq!(watcher.poll().unwrap(), vec![
+            Delta::Removed(dev("a", "1")),
+            Delta::Changed(dev("b", "2")),
+            Delta::Added(dev("d", "1")),
+            Delta::Added(dev("e", "1")),
+        ]);
+        assert_eq!(watcher.devices().len(), 4);
+    }
+
+    #[test]
+    fn test_failed_resync() {
+        let mut watcher = watcher(vec![dev("a", "1")]);
+        {
+            let source = watcher.source_mut();
+            source.devices = vec![dev("b", "1")];
+            source.fail_scan = true;
+            source.queue.push_back(Ok((event(Action::Add, 50), dev("b", "1"))));
+            source.queue.push_back(Err(Error::Overflow));
+        }
+        // The changes made before the failed rescan are kept and the error is reported next.
+        assert_eq!(watcher.poll().unwrap(), vec![Delta::Added(dev("b", "1"))]);
+        assert_eq!(watcher.devices().len(), 2);
+        assert_eq!(watcher.poll(), Err(error::os(EIO, "scan", None)));
+        assert_eq!(watcher.poll(), Err(error::os(EIO, "scan", None)));
+
+        // Once scanning works again, the rescan catches up.
+        watcher.source_mut().fail_scan = false;
+        assert_eq!(watcher.poll().unwrap(), vec![Delta::Removed(dev("a", "1"))]);
+        assert_eq!(watcher.poll().unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_receive_error_kept() {
+        let mut watcher = watcher(vec![]);
+        {
+            let queue = &mut watcher.source_mut().queue;
+            queue.push_back(Ok((event(Action::Add, 60), dev("a", "1"))));
+            queue.push_back(Err(error::os(EIO, "receive", None)));
+            queue.push_back(Ok((event(Action::Add, 61), dev("b", "1"))));
+        }
+        assert_eq!(watcher.poll().unwrap(), vec![Delta::Added(dev("a", "1"))]);
+        assert_eq!(watcher.poll(), Err(error::os(EIO, "receive", None)));
+        assert_eq!(watcher.poll().unwrap(), vec![Delta::Added(dev("b", "1"))]);
+    }
+}